@@ -6,7 +6,16 @@ mod http;
 pub enum Error {
     Io(std::io::Error),
     Db(rusqlite::Error),
-    Http(crate::http::Error),
+    Http(lib::http::Error),
+}
+
+impl lib::http::ErrorStatus for Error {
+    fn status(&self) -> lib::http::StatusCode {
+        match self {
+            Self::Http(e) => e.status(),
+            _ => lib::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
 }
 
 impl From<std::io::Error> for Error {
@@ -21,8 +30,8 @@ impl From<rusqlite::Error> for Error {
     }
 }
 
-impl From<crate::http::Error> for Error {
-    fn from(e: crate::http::Error) -> Self {
+impl From<lib::http::Error> for Error {
+    fn from(e: lib::http::Error) -> Self {
         Self::Http(e)
     }
 }
@@ -66,11 +75,7 @@ fn serve() -> Result<(), Error> {
 
     println!("HTTP server is running on {}", &addr);
 
-    let listener = std::net::TcpListener::bind(addr)?;
-
-    for stream in listener.incoming() {
-        http::handle_connection(stream?)?;
-    }
+    lib::http::Server::new(&addr, http::router()).run()?;
 
     Ok(())
 }
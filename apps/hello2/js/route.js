@@ -0,0 +1,29 @@
+// Builds URLs of named routes from the route table the server embeds in the page, the same way
+// `Urls::url_for` does on the server. Parameters the route doesn't use go to the query string.
+export default function route(name, params = {}) {
+    const route = window.Routes.routes[name]
+
+    if (!route) {
+        throw new Error(`Route [${name}] is not defined`)
+    }
+
+    const query = { ...params }
+
+    const path = route.uri.replace(/\{(\w+)(\*?)\}/g, (_, parameter, wildcard) => {
+        if (!(parameter in params)) {
+            throw new Error(`Route [${name}] requires parameter [${parameter}]`)
+        }
+
+        delete query[parameter]
+
+        const value = String(params[parameter])
+
+        return wildcard
+            ? value.split('/').map(encodeURIComponent).join('/')
+            : encodeURIComponent(value)
+    })
+
+    const search = new URLSearchParams(query).toString()
+
+    return '/' + path + (search ? '?' + search : '')
+}
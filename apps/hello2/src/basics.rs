@@ -1,11 +1,29 @@
+use lib::http::{ErrorStatus, StatusCode};
+
 #[derive(Debug)]
 pub enum Error {
+    Crypt(lib::crypt::Error),
     Db(rusqlite::Error),
     Http(lib::http::Error),
     Io(std::io::Error),
     Json(serde_json::Error),
 }
 
+impl ErrorStatus for Error {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::Http(e) => e.status(),
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<lib::crypt::Error> for Error {
+    fn from(e: lib::crypt::Error) -> Self {
+        Self::Crypt(e)
+    }
+}
+
 impl From<rusqlite::Error> for Error {
     fn from(e: rusqlite::Error) -> Self {
         Self::Db(e)
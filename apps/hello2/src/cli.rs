@@ -2,9 +2,12 @@ mod list;
 mod serve;
 
 use crate::basics::Result;
+use crate::http;
 use lib::cli;
 use lib::cli::Command;
 use lib::cli::Console;
+use lib::crypt::Keys;
+use lib::http::FileStore;
 use std::io::stdout;
 
 pub fn run() -> Result<()> {
@@ -30,7 +33,14 @@ pub mod fake {
 
 fn dispatch(command: &Command, console: &mut Console) -> Result<()> {
     match command.name.as_str() {
+        "key:generate" => cli::key_generate(console)?,
         "list" => list::run(console)?,
+        // Listing routes handles no request, so it doesn't need the application key.
+        "route:list" => {
+            let router = http::routes(Keys::new(b""), FileStore::new("storage/sessions"));
+
+            cli::route_list(command, console, &router)?
+        }
         "serve" => serve::run(console)?,
         _ => cli::not_found(command, console)?,
     };
@@ -4,7 +4,9 @@ use lib::cli::Console;
 pub fn run(console: &mut Console) -> Result<()> {
     console.write(
         "
+key:generate                Set the application key
 list                        List available commands
+route:list                  List all registered routes
 serve                       Run HTTP server
     
 ",
@@ -1,6 +1,7 @@
 use crate::basics::Result;
 use crate::http;
 use lib::cli::Console;
+use lib::http::Server;
 
 pub fn run(console: &mut Console) -> Result<()> {
     let port = std::env::var("APP_PORT").unwrap_or("8000".to_string());
@@ -8,11 +9,7 @@ pub fn run(console: &mut Console) -> Result<()> {
 
     console.writeln(format!("HTTP server is running on {}", &addr).as_str())?;
 
-    let listener = std::net::TcpListener::bind(addr)?;
-
-    for stream in listener.incoming() {
-        http::handle_connection(console, &stream?)?;
-    }
+    Server::new(&addr, http::router()?).run()?;
 
     Ok(())
 }
@@ -1,58 +1,50 @@
 mod home;
 
-use crate::basics::Result;
-use lib::cli::Console;
-use lib::http::Request;
-use lib::http::Response;
-use std::net::TcpStream;
-
-pub fn handle_connection(console: &mut Console, stream: &TcpStream) -> Result<()> {
-    if let Err(err) = handle_connection_but_not_errors(console, &stream) {
-        let response = Response::new(500, "Server error".to_string(), format!("{:?}", err));
-
-        let _ = response.send(&stream);
-    }
-
-    Ok(())
+use crate::basics::{Error, Result};
+use lib::crypt::Keys;
+use lib::http::middleware::{Log, RenderErrors};
+use lib::http::{Compression, Csrf, FileStore, Router, Sessions, Store};
+
+pub fn router() -> Result<Router<Error>> {
+    Ok(routes(
+        Keys::from_env()?,
+        FileStore::new("storage/sessions"),
+    ))
 }
 
-fn handle_connection_but_not_errors(console: &mut Console, stream: &TcpStream) -> Result<()> {
-    let mut request = Request::receive_headers(&stream)?;
-
-    if request.headers.get("x-requested-with").is_some() {
-        console.write("XHR ")?;
-    }
-    
-    console.write(format!("{} {}", request.method, request.uri).as_str())?;
+/// Builds the router around the given keys and session store, so that tests can bring their own.
+pub fn routes<S: Store + 'static>(keys: Keys, sessions: S) -> Router<Error> {
+    let mut router = Router::new();
 
-    let response = handle(&mut request)?;
+    router
+        .middleware(Log)
+        .middleware(Compression::new())
+        .middleware(RenderErrors);
 
-    response.send(&stream)?;
+    // Assets are cached publicly, so they must not start sessions or carry cookies.
+    router.files("/build", "public/build");
 
-    console.writeln(format!(" -> {}", response.status).as_str())?;
+    router
+        .group("/")
+        .middleware(Sessions::new(sessions, keys.clone()))
+        .middleware(Csrf::new(keys))
+        .routes(|router| {
+            router.get("/", home::show::handle).name("home");
+        });
 
-    Ok(())
+    router
 }
 
 #[cfg(test)]
 pub mod fake {
-    use lib::http::{Fake, Request};
+    use lib::crypt::Keys;
+    use lib::http::{Fake, FileStore, Request};
 
     pub fn get(uri: &'static str) -> Fake {
+        let dir = tempfile::tempdir().unwrap();
+        let router = super::routes(Keys::new(b"testing"), FileStore::new(dir.path()));
         let mut request = Request::new("GET".to_string(), uri.to_string());
 
-        Fake::new(super::handle(&mut request).unwrap())
-    }
-}
-
-fn handle(request: &mut Request) -> Result<Response> {
-    route(request)
-}
-
-fn route(request: &mut Request) -> Result<Response> {
-    if request.is("GET /") {
-        home::show::handle(request)
-    } else {
-        Ok(Response::not_found())
+        Fake::new(router.handle(&mut request).unwrap())
     }
 }
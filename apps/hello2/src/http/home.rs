@@ -5,22 +5,19 @@ pub mod show {
     use lib::http::Response;
     use serde_json::json;
 
-    pub fn handle(request: &Request) -> Result<Response> {
-        Ok(inertia::response(request, "Home", json!({
+    pub fn handle(request: &mut Request) -> Result<Response> {
+        inertia::response(request, "Home", json!({
             "foo": "bar"
-        }).to_string()))
+        }))
     }
 
     #[cfg(test)]
     mod tests {
         use crate::http::fake;
-        use lib::env;
 
         #[test]
         fn it_shows_hello() {
-            env::load(".env.testing");
-
-            assert!(fake::get("/").see("Hello"));
+            assert!(fake::get("/").see("Home"));
         }
     }
 }
@@ -1,23 +1,29 @@
+use crate::basics::Result;
 use lib::http::escape_html;
 use lib::http::Request;
 use lib::http::Response;
-
-pub fn response(request: &Request, component: &str, props: String) -> Response {
-    let url = &request.uri;
-    let version = "".to_string();
-
-    let mut data = format!("{{\"component\":\"{component}\",\"props\":{props},\"url\":\"{url}\",\"version\":\"{version}\"}}");
-
-    if request.headers.get("x-inertia").is_some() {
-        let mut response = Response::json(data);
+use serde_json::json;
+use serde_json::Value;
+
+pub fn response(request: &Request, component: &str, props: Value) -> Result<Response> {
+    let page = json!({
+        "component": component,
+        "props": props,
+        "url": request.uri,
+        "version": "",
+    });
+
+    if request.headers.contains_key("X-Inertia") {
+        let mut response = Response::json_value(&page)?;
         
-        response.header("Vary".to_string(), "Accept".to_string());
-        response.header("X-Inertia".to_string(), "true".to_string());
+        response.header("Vary", "Accept")?;
+        response.header("X-Inertia", "true")?;
 
-        return response;
+        return Ok(response);
     }
 
-    data = escape_html(data);
+    let data = escape_html(page.to_string());
+    let routes = request.urls.to_json();
 
     let vite_port = std::env::var("VITE_PORT").unwrap_or("5173".to_string());
 
@@ -26,18 +32,19 @@ pub fn response(request: &Request, component: &str, props: String) -> Response {
 <script type=\"module\" src=\"http://127.0.0.1:{vite_port}/js/app.js\"></script>
 ");
 
-    Response::html(format!(
+    Ok(Response::html(format!(
             "<!DOCTYPE html>
 <html lang=\"en\">
     <head>
         <meta charset=\"utf-8\">
         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
         <link rel=\"stylesheet\" href=\"https://rsms.me/inter/inter.css\">
+        <script>window.Routes = {routes};</script>
         {vite}
     </head>
     <body>
         <div id=\"app\" data-page=\"{data}\"></div>
     </body>
 </html>
-"))
+")))
 }
@@ -7,6 +7,7 @@ const env = loadEnv("", process.cwd(), "");
 /** @type {import('vite').UserConfig} */
 export default defineConfig({
   build: {
+    outDir: 'public/build',
     rollupOptions: {
       input: {
         main: './js/app.js',
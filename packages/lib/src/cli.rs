@@ -1,3 +1,4 @@
+use crate::http::{json_string, Router};
 use std::{
     env, io,
     io::{Result, Stdout, Write},
@@ -5,21 +6,47 @@ use std::{
 
 pub struct Command {
     pub name: String,
+    /// The arguments after the command name.
+    pub args: Vec<String>,
 }
 
 impl Command {
     pub fn from_args() -> Command {
-        let args = env::args();
-        let name = args.skip(1).next().unwrap_or("list".to_string());
+        let mut args = env::args().skip(1);
+        let name = args.next().unwrap_or("list".to_string());
 
-        Command { name }
+        Command {
+            name,
+            args: args.collect(),
+        }
     }
 
     pub fn from_str(s: &str) -> Command {
         let mut args = s.split_whitespace();
         let name = args.next().unwrap_or("list").to_string();
 
-        Command { name }
+        Command {
+            name,
+            args: args.map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    /// Returns the value of an option given as `--name=value` or `--name value`.
+    pub fn option(&self, name: &str) -> Option<&str> {
+        let flag = format!("--{name}");
+
+        self.args.iter().enumerate().find_map(|(i, arg)| {
+            match arg.strip_prefix(&flag)?.strip_prefix('=') {
+                Some(value) => Some(value),
+                None if arg == &flag => self.args.get(i + 1).map(|value| value.as_str()),
+                None => None,
+            }
+        })
+    }
+
+    /// Tells whether a `--name` flag is given.
+    pub fn flag(&self, name: &str) -> bool {
+        self.args.contains(&format!("--{name}"))
     }
 }
 
@@ -72,3 +99,142 @@ pub fn not_found(command: &Command, console: &mut Console) -> Result<()> {
 
     Ok(())
 }
+
+/// Generates a new `APP_KEY` and writes it to `.env`. The key it replaces is added to
+/// `APP_PREVIOUS_KEYS`, so that cookies signed with it stay valid until they expire.
+pub fn key_generate(console: &mut Console) -> Result<()> {
+    let key = crate::crypt::generate_key();
+
+    if let Ok(current) = env::var("APP_KEY") {
+        let previous = match env::var("APP_PREVIOUS_KEYS") {
+            Ok(previous) if !previous.is_empty() => format!("{current},{previous}"),
+            _ => current,
+        };
+
+        crate::env::write(".env", "APP_PREVIOUS_KEYS", &previous)?;
+    }
+
+    crate::env::write(".env", "APP_KEY", &key)?;
+
+    console.writeln("Application key set.")?;
+
+    Ok(())
+}
+
+/// Prints the routes of `router` with their method, pattern, name and middleware, the router's
+/// own included. Routes can be filtered with `--method=GET` and `--path=/admin`, and `--json`
+/// prints them as a JSON array.
+pub fn route_list<E>(command: &Command, console: &mut Console, router: &Router<E>) -> Result<()> {
+    let method = command.option("method").map(|m| m.to_uppercase());
+    let path = command.option("path");
+    let middleware = router.middleware_names();
+    let routes: Vec<(String, String, String, Vec<String>)> = router
+        .routes()
+        .iter()
+        .filter(|route| method.as_ref().is_none_or(|m| &route.method == m))
+        .filter(|route| path.is_none_or(|p| route.pattern.starts_with(p)))
+        .map(|route| {
+            (
+                route.method.clone(),
+                route.pattern.clone(),
+                route.name.clone().unwrap_or_default(),
+                [middleware.clone(), route.middleware_names()].concat(),
+            )
+        })
+        .collect();
+
+    if command.flag("json") {
+        let routes: Vec<String> = routes
+            .iter()
+            .map(|(method, pattern, name, middleware)| {
+                let middleware: Vec<String> = middleware.iter().map(|m| json_string(m)).collect();
+
+                format!(
+                    "{{\"method\":{},\"pattern\":{},\"name\":{},\"middleware\":[{}]}}",
+                    json_string(method),
+                    json_string(pattern),
+                    if name.is_empty() {
+                        "null".to_string()
+                    } else {
+                        json_string(name)
+                    },
+                    middleware.join(",")
+                )
+            })
+            .collect();
+
+        console.writeln(&format!("[{}]", routes.join(",")))?;
+
+        return Ok(());
+    }
+
+    let header = [
+        "Method".to_string(),
+        "Pattern".to_string(),
+        "Name".to_string(),
+        "Middleware".to_string(),
+    ];
+    let rows: Vec<[String; 4]> = routes
+        .into_iter()
+        .map(|(method, pattern, name, middleware)| [method, pattern, name, middleware.join(", ")])
+        .collect();
+    let mut widths = [0; 4];
+
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+
+        console.writeln(line.join("  ").trim_end())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{route_list, Command, Console};
+    use crate::http::middleware::Log;
+    use crate::http::{Compression, Response, Router};
+
+    fn run(args: &str) -> String {
+        let mut router: Router<()> = Router::new();
+
+        router.middleware(Compression::new());
+        router
+            .get("/", |_| Ok(Response::plain_text("home".to_string())))
+            .name("home");
+        router.group("/admin").middleware(Log).routes(|router| {
+            router.post("/posts", |_| Ok(Response::plain_text("saved".to_string())));
+        });
+
+        let mut console = Console::Fake(Vec::new());
+
+        route_list(&Command::from_str(args), &mut console, &router).unwrap();
+
+        console.output()
+    }
+
+    #[test]
+    fn it_lists_routes() {
+        assert_eq!(
+            run("route:list"),
+            "Method  Pattern       Name  Middleware\n\
+             GET     /             home  Compression\n\
+             POST    /admin/posts        Compression, Log\n"
+        );
+        assert_eq!(
+            run("route:list --method post --json"),
+            "[{\"method\":\"POST\",\"pattern\":\"/admin/posts\",\"name\":null,\"middleware\":[\"Compression\",\"Log\"]}]\n"
+        );
+        assert!(!run("route:list --path=/admin").contains("home"));
+    }
+}
@@ -0,0 +1,193 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+#[derive(Debug)]
+pub enum Error {
+    MissingKey,
+    InvalidKey,
+}
+
+/// The app's secret key, which signs and encrypts values, together with the keys it replaced.
+///
+/// New values always use the current key. Values made with a previous key are still accepted,
+/// so that rotating `APP_KEY` doesn't log everybody out at once.
+#[derive(Clone)]
+pub struct Keys {
+    current: Vec<u8>,
+    previous: Vec<Vec<u8>>,
+}
+
+impl Keys {
+    pub fn new(current: &[u8]) -> Keys {
+        Keys {
+            current: current.to_vec(),
+            previous: Vec::new(),
+        }
+    }
+
+    /// Also accepts values made with the given key.
+    pub fn previous(mut self, key: &[u8]) -> Keys {
+        self.previous.push(key.to_vec());
+
+        self
+    }
+
+    /// Reads `APP_KEY` and the comma-separated `APP_PREVIOUS_KEYS`, both in the `base64:...`
+    /// form `key:generate` writes.
+    pub fn from_env() -> Result<Keys, Error> {
+        let current = std::env::var("APP_KEY").map_err(|_| Error::MissingKey)?;
+        let mut keys = Keys::new(&parse(&current)?);
+
+        if let Ok(previous) = std::env::var("APP_PREVIOUS_KEYS") {
+            for key in previous.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                keys = keys.previous(&parse(key)?);
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn all(&self) -> impl Iterator<Item = &[u8]> {
+        std::iter::once(self.current.as_slice()).chain(self.previous.iter().map(|k| k.as_slice()))
+    }
+
+    /// Returns the value followed by its HMAC-SHA256 signature, both base64url-encoded, so the
+    /// result is safe in a cookie.
+    pub fn sign(&self, value: &str) -> String {
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(value),
+            URL_SAFE_NO_PAD.encode(mac(&self.current, value.as_bytes()).finalize().into_bytes())
+        )
+    }
+
+    /// Returns the value if any of the keys signed it.
+    pub fn verify(&self, signed: &str) -> Option<String> {
+        let (value, signature) = signed.split_once('.')?;
+        let value = URL_SAFE_NO_PAD.decode(value).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+        if !self
+            .all()
+            .any(|key| mac(key, &value).verify_slice(&signature).is_ok())
+        {
+            return None;
+        }
+
+        String::from_utf8(value).ok()
+    }
+
+    /// Encrypts and authenticates the value with AES-256-GCM under a random nonce. The result is
+    /// base64url-encoded.
+    pub fn encrypt(&self, value: &str) -> String {
+        let mut nonce = [0u8; 12];
+
+        getrandom::getrandom(&mut nonce).expect("the OS random number generator is unavailable");
+
+        let ciphertext = cipher(&self.current)
+            .encrypt(Nonce::from_slice(&nonce), value.as_bytes())
+            .expect("AES-GCM encrypts any value that fits in memory");
+
+        URL_SAFE_NO_PAD.encode([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    /// Returns the value if any of the keys encrypted it and nobody changed it since.
+    pub fn decrypt(&self, encrypted: &str) -> Option<String> {
+        let bytes = URL_SAFE_NO_PAD.decode(encrypted).ok()?;
+
+        if bytes.len() < 12 {
+            return None;
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(12);
+
+        self.all()
+            .find_map(|key| {
+                cipher(key)
+                    .decrypt(Nonce::from_slice(nonce), ciphertext)
+                    .ok()
+            })
+            .and_then(|value| String::from_utf8(value).ok())
+    }
+}
+
+/// Returns a new random key in the form `APP_KEY` expects.
+pub fn generate_key() -> String {
+    let mut key = [0u8; 32];
+
+    getrandom::getrandom(&mut key).expect("the OS random number generator is unavailable");
+
+    format!("base64:{}", STANDARD.encode(key))
+}
+
+fn parse(key: &str) -> Result<Vec<u8>, Error> {
+    let key = key.strip_prefix("base64:").ok_or(Error::InvalidKey)?;
+    let key = STANDARD.decode(key).map_err(|_| Error::InvalidKey)?;
+
+    if key.len() != 32 {
+        return Err(Error::InvalidKey);
+    }
+
+    Ok(key)
+}
+
+fn mac(key: &[u8], value: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+
+    mac.update(value);
+
+    mac
+}
+
+fn cipher(key: &[u8]) -> Aes256Gcm {
+    // Keys passed to `Keys::new` may have any length, so they're hashed to the 32 bytes AES-256
+    // needs.
+    use sha2::Digest;
+
+    Aes256Gcm::new(&Sha256::digest(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypt::{generate_key, Keys};
+
+    #[test]
+    fn it_signs_and_encrypts_values() {
+        let keys = Keys::new(b"secret");
+
+        let signed = keys.sign("user=1");
+        assert_eq!(keys.verify(&signed), Some("user=1".to_string()));
+        assert_eq!(keys.verify(&signed.replace('.', "A.")), None);
+        assert_eq!(Keys::new(b"other").verify(&signed), None);
+
+        let encrypted = keys.encrypt("user=1");
+        assert!(!encrypted.contains("user"));
+        assert_ne!(keys.encrypt("user=1"), encrypted);
+        assert_eq!(keys.decrypt(&encrypted), Some("user=1".to_string()));
+        assert_eq!(Keys::new(b"other").decrypt(&encrypted), None);
+    }
+
+    #[test]
+    fn it_accepts_values_made_with_previous_keys() {
+        let old = Keys::new(b"old");
+        let keys = Keys::new(b"new").previous(b"old");
+
+        assert_eq!(keys.verify(&old.sign("a")), Some("a".to_string()));
+        assert_eq!(keys.decrypt(&old.encrypt("a")), Some("a".to_string()));
+        assert_eq!(old.verify(&keys.sign("a")), None);
+    }
+
+    #[test]
+    fn it_generates_keys_in_app_key_form() {
+        let key = generate_key();
+
+        assert!(key.starts_with("base64:"));
+        assert_eq!(super::parse(&key).unwrap().len(), 32);
+        assert!(super::parse("secret").is_err());
+    }
+}
@@ -62,3 +62,43 @@ fn load_contents(contents: String) {
         _ => {}
     }
 }
+
+/// Sets a variable in the given `.env` file, replacing its line if there is one and adding it at
+/// the end otherwise. The file is created if it doesn't exist.
+pub fn write(path: &str, name: &str, value: &str) -> std::io::Result<()> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    let prefix = format!("{name}=");
+    let mut lines: Vec<String> = contents.lines().map(|line| line.to_string()).collect();
+
+    match lines.iter_mut().find(|line| line.starts_with(&prefix)) {
+        Some(line) => *line = format!("{prefix}{value}"),
+        None => lines.push(format!("{prefix}{value}")),
+    }
+
+    std::fs::write(path, lines.join("\n") + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn it_writes_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        let path = path.to_str().unwrap();
+
+        std::fs::write(path, "APP_PORT=8000\nAPP_KEY=old\n# comment\n").unwrap();
+
+        super::write(path, "APP_KEY", "new").unwrap();
+        super::write(path, "APP_WORKERS", "4").unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "APP_PORT=8000\nAPP_KEY=new\n# comment\nAPP_WORKERS=4\n"
+        );
+    }
+}
@@ -1,3 +1,36 @@
+mod compression;
+pub mod cookie;
+mod cors;
+mod csrf;
+pub mod date;
+mod files;
+mod headers;
+pub mod middleware;
+pub mod multipart;
+mod router;
+mod server;
+pub mod session;
+mod status;
+mod url;
+
+pub use compression::Compression;
+pub use cookie::{Cookie, Cookies, SameSite};
+pub use cors::Cors;
+pub use csrf::Csrf;
+pub use files::{mime_type, StaticFiles};
+pub use headers::HeaderMap;
+pub use middleware::{Middleware, Next};
+pub use multipart::{Multipart, Part, UploadedFile};
+pub use router::{Group, Handler, Route, Router};
+pub use server::Server;
+#[cfg(feature = "sqlite")]
+pub use session::SqliteStore;
+pub use session::{FileStore, Session, Sessions, Store};
+pub use status::StatusCode;
+pub(crate) use url::json_string;
+pub use url::Urls;
+
+use crate::crypt::Keys;
 use std::io;
 use std::io::prelude::*;
 
@@ -7,14 +40,117 @@ pub enum Error {
     MethodExpected,
     UriExpected,
     ParameterExpected(&'static str),
+    /// A route parameter couldn't be converted to the requested type.
+    InvalidParameter(&'static str),
     HeaderExpected,
+    InvalidContentLength,
+    BodyTooLarge,
+    InvalidUtf8,
+    InvalidChunk,
+    UnsupportedTransferEncoding,
+    InvalidHeader,
+    /// A cookie to set has a name that isn't a token, or characters in its value or attributes
+    /// that would change its meaning.
+    InvalidCookie,
+    /// Middleware that needs the session, such as `Csrf`, runs without `Sessions` around it.
+    SessionExpected,
+    InvalidMultipart,
+    UnsupportedMediaType,
+    /// The body isn't valid in the `Content-Encoding` it claims.
+    InvalidEncoding,
+    /// The request line isn't `METHOD target HTTP/1.x`.
+    InvalidRequestLine,
+    /// The request is made with an HTTP version other than 1.0 or 1.1.
+    UnsupportedVersion,
+    /// The request line is longer than `Limits::max_request_line_size`.
+    UriTooLong,
+    /// The headers exceed `Limits::max_header_size` or `Limits::max_headers`.
+    HeadersTooLarge,
+    /// A received header is folded, has a name that isn't a token, or a control character in
+    /// its value.
+    InvalidRequestHeader,
+    /// The body isn't valid JSON.
+    #[cfg(feature = "serde")]
+    MalformedJson(serde_json::Error),
+    /// The body is valid JSON, but not of the expected shape.
+    #[cfg(feature = "serde")]
+    UnprocessableJson(serde_json::Error),
+    #[cfg(feature = "serde")]
+    SerializeJson(serde_json::Error),
+}
+
+/// Errors that know the status of the response they should turn into. `RenderErrors` uses it
+/// to answer client mistakes, such as a malformed body, with a `4xx` rather than a `500`.
+pub trait ErrorStatus {
+    fn status(&self) -> StatusCode;
+}
+
+impl ErrorStatus for Error {
+    fn status(&self) -> StatusCode {
+        match self {
+            Error::Io(_)
+            | Error::InvalidHeader
+            | Error::InvalidCookie
+            | Error::SessionExpected
+            | Error::ParameterExpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::InvalidParameter(_) => StatusCode::NOT_FOUND,
+            Error::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Error::UnsupportedVersion => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            Error::UriTooLong => StatusCode::URI_TOO_LONG,
+            Error::HeadersTooLarge => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            #[cfg(feature = "serde")]
+            Error::UnprocessableJson(_) => StatusCode::UNPROCESSABLE_CONTENT,
+            #[cfg(feature = "serde")]
+            Error::SerializeJson(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
 }
 
 pub struct Request {
     pub method: String,
     pub uri: String,
+    pub version: String,
     pub parameters: Parameters,
-    pub headers: Headers,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+    pub trailers: HeaderMap,
+    /// Set by the `Sessions` middleware.
+    pub session: Option<Session>,
+    /// The named routes of the router handling the request.
+    pub urls: std::sync::Arc<Urls>,
+}
+
+/// Upper bounds applied while receiving a request.
+#[derive(Clone)]
+pub struct Limits {
+    /// Maximum number of body bytes accepted; larger bodies are rejected with
+    /// `Error::BodyTooLarge` before any of them are read. Bodies are buffered whole, multipart
+    /// uploads included, so this is what bounds the memory a request takes.
+    pub max_body_size: usize,
+    /// Maximum size of a body sent with `Content-Encoding: gzip` or `deflate` once it's decoded,
+    /// so that a small compressed body can't expand to exhaust memory.
+    pub max_decoded_body_size: usize,
+    /// Maximum length of the request line, beyond which requests are rejected with
+    /// `Error::UriTooLong`.
+    pub max_request_line_size: usize,
+    /// Maximum combined length of the header lines.
+    pub max_header_size: usize,
+    /// Maximum number of headers.
+    pub max_headers: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_body_size: 8 * 1024 * 1024,
+            max_decoded_body_size: 32 * 1024 * 1024,
+            max_request_line_size: 8 * 1024,
+            max_header_size: 16 * 1024,
+            max_headers: 100,
+        }
+    }
 }
 
 enum State {
@@ -26,87 +162,408 @@ enum State {
 }
 
 pub type Parameters = std::collections::HashMap<String, String>;
-pub type Headers = std::collections::HashMap<String, String>;
+
+/// Decoded `name=value` pairs of a query string, in their original order. The same name may
+/// appear several times, as in `?tag=a&tag=b`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+}
+
+impl Query {
+    pub fn parse(s: &str) -> Query {
+        let mut query = Query::default();
+
+        for pair in s.split('&') {
+            if pair.is_empty() {
+                continue;
+            }
+
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+
+            query
+                .pairs
+                .push((decode_form_component(name), decode_form_component(value)));
+        }
+
+        query
+    }
+
+    /// Returns the first value of the given name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns all values of the given name, in order.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.pairs
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.pairs.iter().any(|(n, _)| n == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+/// Decodes `%XX` escapes. Malformed escapes are kept as they are, and invalid UTF-8 is replaced
+/// with U+FFFD.
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        if bytes[offset] == b'%'
+            && offset + 2 < bytes.len()
+            && bytes[offset + 1].is_ascii_hexdigit()
+            && bytes[offset + 2].is_ascii_hexdigit()
+        {
+            let hex = std::str::from_utf8(&bytes[offset + 1..offset + 3]).unwrap_or("");
+
+            result.push(u8::from_str_radix(hex, 16).unwrap_or(0));
+            offset += 3;
+            continue;
+        }
+
+        result.push(bytes[offset]);
+        offset += 1;
+    }
+
+    String::from_utf8_lossy(&result).to_string()
+}
+
+/// Encodes everything but unreserved characters as `%XX`, so the result is safe in a path
+/// segment, a query string or a cookie value.
+pub fn percent_encode(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                result.push(byte as char)
+            }
+            _ => result.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    result
+}
+
+/// Decodes a query string or form component, where `+` stands for a space.
+fn decode_form_component(s: &str) -> String {
+    percent_decode(&s.replace('+', " "))
+}
 
 impl Request {
     pub fn new(method: String, uri: String) -> Request {
         Request {
             method,
             uri,
+            version: "HTTP/1.1".to_string(),
             parameters: Parameters::new(),
-            headers: Headers::new(),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+            trailers: HeaderMap::new(),
+            session: None,
+            urls: std::sync::Arc::default(),
         }
     }
 
-    pub fn receive_headers(stream: &std::net::TcpStream) -> Result<Request, Error> {
-        let print_headers = false;
+    pub fn receive<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<Request, Error> {
+        let mut request = Request::receive_headers(reader, limits)?;
+
+        request.receive_body(reader, limits)?;
+
+        Ok(request)
+    }
+
+    /// Reads the request line and the headers, following RFC 9112 strictly: the request line
+    /// must be `METHOD target HTTP/1.x`, header names must be tokens, and header values may not
+    /// be folded over several lines.
+    pub fn receive_headers<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<Request, Error> {
+        let mut line = read_head_line(reader, limits.max_request_line_size, Error::UriTooLong)?;
 
-        enum State {
-            StartLine,
-            Headers(Request),
+        // A stray line break may follow the body of the previous request on the connection.
+        if line.is_empty() {
+            line = read_head_line(reader, limits.max_request_line_size, Error::UriTooLong)?;
         }
-        let lines = std::io::BufReader::new(stream).lines();
-        let mut state = State::StartLine;
 
-        if print_headers {
-            println!("");
+        let mut request = parse_request_line(&line)?;
+
+        request.headers = receive_fields(reader, limits)?;
+
+        Ok(request)
+    }
+
+    fn receive_body<R: BufRead>(&mut self, reader: &mut R, limits: &Limits) -> Result<(), Error> {
+        let transfer_encoding = self.headers.get_all("transfer-encoding");
+
+        if !transfer_encoding.is_empty() {
+            // Other transfer codings, even ahead of `chunked`, would reach handlers undecoded.
+            if transfer_encoding.len() > 1
+                || !transfer_encoding[0].trim().eq_ignore_ascii_case("chunked")
+            {
+                return Err(Error::UnsupportedTransferEncoding);
+            }
+
+            self.receive_chunked_body(reader, limits)?;
+        } else {
+            let Some(length) = content_length(&self.headers)? else {
+                return Ok(());
+            };
+
+            if length > limits.max_body_size {
+                return Err(Error::BodyTooLarge);
+            }
+
+            self.body = vec![0; length];
+            reader.read_exact(&mut self.body).map_err(Error::Io)?;
         }
 
-        for line in lines {
-            match line {
-                Ok(line) => {
-                    if line.is_empty() {
-                        break;
-                    }
+        self.decode_body(limits)
+    }
 
-                    if print_headers {
-                        println!("{}", line);
-                    }
+    /// Undoes the `Content-Encoding` of the body, so that handlers always see it as sent.
+    /// Encodings other than `gzip` and `deflate` are rejected with `UnsupportedMediaType`.
+    fn decode_body(&mut self, limits: &Limits) -> Result<(), Error> {
+        // Repeated headers are one list, in the order they came.
+        let codings: Vec<String> = self
+            .headers
+            .get_all("content-encoding")
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(|coding| coding.trim().to_ascii_lowercase())
+            .filter(|coding| !coding.is_empty() && coding != "identity")
+            .collect();
 
-                    match state {
-                        State::StartLine => {
-                            let mut parts = line.split_whitespace();
-                            let method = parts.next().ok_or(Error::MethodExpected)?;
-                            let uri = parts.next().ok_or(Error::UriExpected)?;
-                            let request = Request::new(method.to_string(), uri.to_string());
+        // Encodings are listed in the order they were applied, so they're undone from the end.
+        for coding in codings.iter().rev() {
+            let body = self.body.as_slice();
+            let decoder: Box<dyn Read + '_> = match coding.as_str() {
+                "gzip" | "x-gzip" => Box::new(flate2::read::GzDecoder::new(body)),
+                "deflate" => Box::new(flate2::read::ZlibDecoder::new(body)),
+                _ => return Err(Error::UnsupportedMediaType),
+            };
+            let mut decoded = Vec::new();
 
-                            state = State::Headers(request);
-                        }
+            // One byte more than allowed is read to tell a body at the limit from a larger one.
+            decoder
+                .take(limits.max_decoded_body_size as u64 + 1)
+                .read_to_end(&mut decoded)
+                .map_err(|_| Error::InvalidEncoding)?;
 
-                        State::Headers(mut request) => {
-                            let pos = line.find(':').ok_or(Error::HeaderExpected)?;
+            if decoded.len() > limits.max_decoded_body_size {
+                return Err(Error::BodyTooLarge);
+            }
 
-                            request.headers.insert(
-                                line[..pos].trim().to_lowercase().to_string(),
-                                line[pos + 1..].trim().to_string(),
-                            );
+            self.body = decoded;
+        }
 
-                            state = State::Headers(request);
-                        }
-                    }
+        self.headers.remove("content-encoding");
+
+        Ok(())
+    }
+
+    fn receive_chunked_body<R: BufRead>(
+        &mut self,
+        reader: &mut R,
+        limits: &Limits,
+    ) -> Result<(), Error> {
+        loop {
+            let line = read_head_line(reader, MAX_CHUNK_LINE, Error::InvalidChunk)?;
+
+            // Chunk extensions (`;name=value`) carry nothing we use, so they are skipped.
+            let size = line
+                .split(|&c| c == b';')
+                .next()
+                .unwrap_or(&[])
+                .trim_ascii();
+            let size = std::str::from_utf8(size)
+                .ok()
+                .filter(|size| size.bytes().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|size| usize::from_str_radix(size, 16).ok())
+                .ok_or(Error::InvalidChunk)?;
+
+            if size == 0 {
+                break;
+            }
+
+            let offset = self.body.len();
+            if size > limits.max_body_size - offset {
+                return Err(Error::BodyTooLarge);
+            }
+
+            self.body.resize(offset + size, 0);
+            reader
+                .read_exact(&mut self.body[offset..])
+                .map_err(Error::Io)?;
+
+            if !read_head_line(reader, 0, Error::InvalidChunk)?.is_empty() {
+                return Err(Error::InvalidChunk);
+            }
+        }
+
+        self.trailers = receive_fields(reader, limits)?;
+
+        Ok(())
+    }
+
+    /// Returns a route parameter converted to `T`, as in `request.param::<i64>("id")`. Fails
+    /// with `Error::ParameterExpected` if the route has no such parameter, and with
+    /// `Error::InvalidParameter`, which answers 404, if the value doesn't convert.
+    pub fn param<T: std::str::FromStr>(&self, name: &'static str) -> Result<T, Error> {
+        self.parameters
+            .get(name)
+            .ok_or(Error::ParameterExpected(name))?
+            .parse()
+            .map_err(|_| Error::InvalidParameter(name))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn text(&self) -> Result<&str, Error> {
+        std::str::from_utf8(&self.body).map_err(|_| Error::InvalidUtf8)
+    }
+
+    /// Deserializes a JSON body. Fails with `Error::UnsupportedMediaType` unless the body is
+    /// declared as JSON, `Error::MalformedJson` if it isn't valid JSON, and
+    /// `Error::UnprocessableJson` if it doesn't fit `T`.
+    #[cfg(feature = "serde")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, Error> {
+        let is_json = self.headers.get("Content-Type").is_some_and(|t| {
+            let mime = t
+                .split(';')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .to_ascii_lowercase();
+
+            mime == "application/json" || mime.ends_with("+json")
+        });
+
+        if !is_json {
+            return Err(Error::UnsupportedMediaType);
+        }
+
+        serde_json::from_slice(&self.body).map_err(|e| match e.classify() {
+            serde_json::error::Category::Data => Error::UnprocessableJson(e),
+            _ => Error::MalformedJson(e),
+        })
+    }
+
+    /// Tells whether the client is willing to send further requests over the same connection:
+    /// `Connection: close` and `keep-alive` decide, and otherwise HTTP/1.1 keeps the connection
+    /// open while HTTP/1.0 closes it.
+    pub fn keep_alive(&self) -> bool {
+        if let Some(connection) = self.headers.get("connection") {
+            for option in connection.split(',') {
+                if option.trim().eq_ignore_ascii_case("close") {
+                    return false;
                 }
-                Err(e) => {
-                    return Err(Error::Io(e));
+
+                if option.trim().eq_ignore_ascii_case("keep-alive") {
+                    return true;
                 }
             }
         }
 
-        if print_headers {
-            println!("");
-        }
+        self.version == "HTTP/1.1"
+    }
 
-        match state {
-            State::StartLine => Err(Error::MethodExpected),
-            State::Headers(request) => Ok(request),
+    /// Returns the URI without its query string.
+    pub fn path(&self) -> &str {
+        split_uri(&self.uri).0
+    }
+
+    /// Returns the raw query string, without the leading `?`.
+    pub fn query_string(&self) -> &str {
+        split_uri(&self.uri).1
+    }
+
+    pub fn query(&self) -> Query {
+        Query::parse(self.query_string())
+    }
+
+    /// Decodes an `application/x-www-form-urlencoded` body. Other bodies give an empty form.
+    pub fn form(&self) -> Query {
+        let is_form = self.headers.get("Content-Type").is_some_and(|t| {
+            t.split(';')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+        });
+
+        if !is_form {
+            return Query::default();
         }
+
+        Query::parse(&String::from_utf8_lossy(&self.body))
+    }
+
+    /// Starts reading a `multipart/form-data` body from the request's buffered `body`. Fails with
+    /// `Error::InvalidMultipart` if the body is of another type.
+    pub fn multipart(&self) -> Result<Multipart<&[u8]>, Error> {
+        let boundary = self
+            .headers
+            .get("Content-Type")
+            .and_then(multipart::boundary)
+            .ok_or(Error::InvalidMultipart)?;
+
+        Ok(Multipart::new(&self.body, &boundary))
+    }
+
+    /// Returns the cookies sent with the request.
+    pub fn cookies(&self) -> Cookies {
+        self.headers
+            .get("cookie")
+            .map(cookie::parse)
+            .unwrap_or_default()
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().remove(name)
+    }
+
+    /// Returns the value of a cookie set with `Cookie::signed`, or `None` if it's missing or was
+    /// tampered with.
+    pub fn signed_cookie(&self, name: &str, keys: &Keys) -> Option<String> {
+        cookie::unseal(name, keys.verify(&self.cookie(name)?))
+    }
+
+    /// Returns the value of a cookie set with `Cookie::encrypted`, or `None` if it's missing or
+    /// was tampered with.
+    pub fn encrypted_cookie(&self, name: &str, keys: &Keys) -> Option<String> {
+        cookie::unseal(name, keys.decrypt(&self.cookie(name)?))
     }
 
     pub fn is(&mut self, route: &str) -> bool {
         let mut state = State::Method;
         let mut offset = 0;
         let mut route_offset = 0;
-        let method_bytes = self.method.as_bytes();
-        let uri_bytes = self.uri.as_bytes();
+        // `HEAD` requests are answered like `GET` ones, minus the body.
+        let method_bytes = if self.method == "HEAD" && route.starts_with("GET ") {
+            b"GET".as_slice()
+        } else {
+            self.method.as_bytes()
+        };
+        let uri_bytes = split_uri(&self.uri).0.as_bytes();
         let route_bytes = route.as_bytes();
 
         self.parameters.clear();
@@ -115,6 +572,10 @@ impl Request {
             match state {
                 State::Method => match c {
                     b' ' => {
+                        if route_offset != method_bytes.len() {
+                            return false;
+                        }
+
                         state = State::Uri;
                     }
                     _ => {
@@ -163,7 +624,9 @@ impl Request {
                         self.parameters.insert(
                             String::from_utf8_lossy(&route_bytes[parameter_offset..route_offset])
                                 .to_string(),
-                            String::from_utf8_lossy(&uri_bytes[offset..next_offset]).to_string(),
+                            percent_decode(&String::from_utf8_lossy(
+                                &uri_bytes[offset..next_offset],
+                            )),
                         );
 
                         state = State::Uri;
@@ -182,7 +645,7 @@ impl Request {
                                 &route_bytes[parameter_offset..route_offset - 1],
                             )
                             .to_string(),
-                            String::from_utf8_lossy(&uri_bytes[offset..]).to_string(),
+                            percent_decode(&String::from_utf8_lossy(&uri_bytes[offset..])),
                         );
 
                         state = State::Uri;
@@ -204,91 +667,534 @@ impl Request {
     }
 }
 
+/// Splits a request target into its path and query string, dropping any fragment.
+fn split_uri(uri: &str) -> (&str, &str) {
+    let uri = uri.split('#').next().unwrap_or("");
+
+    uri.split_once('?').unwrap_or((uri, ""))
+}
+
+/// Chunk-size lines hold a hex number and extensions we skip, so anything longer is malformed.
+const MAX_CHUNK_LINE: usize = 1024;
+
+/// Reads a line of the request head, without its line break. Lines longer than `max_size` fail
+/// with `too_long`.
+fn read_head_line<R: BufRead>(
+    reader: &mut R,
+    max_size: usize,
+    too_long: Error,
+) -> Result<Vec<u8>, Error> {
+    let mut line = Vec::new();
+
+    // Room is left for the line break, which doesn't count against the limit.
+    reader
+        .take(max_size as u64 + 2)
+        .read_until(b'\n', &mut line)
+        .map_err(Error::Io)?;
+
+    if line.pop() != Some(b'\n') {
+        if line.len() > max_size {
+            return Err(too_long);
+        }
+
+        return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+    }
+
+    // RFC 9112 lets a bare `\n` end a line too.
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    if line.len() > max_size {
+        return Err(too_long);
+    }
+
+    Ok(line)
+}
+
+fn parse_request_line(line: &[u8]) -> Result<Request, Error> {
+    let line = std::str::from_utf8(line).map_err(|_| Error::InvalidRequestLine)?;
+    let mut parts = line.split(' ');
+
+    let method = parts.next().filter(|method| headers::is_token(method));
+    let method = method.ok_or(Error::MethodExpected)?;
+    let uri = parts
+        .next()
+        .filter(|uri| !uri.is_empty() && !uri.bytes().any(|c| c.is_ascii_control()));
+    let uri = uri.ok_or(Error::UriExpected)?;
+    let version = parts.next().ok_or(Error::InvalidRequestLine)?;
+
+    if parts.next().is_some() {
+        return Err(Error::InvalidRequestLine);
+    }
+
+    match version.as_bytes() {
+        b"HTTP/1.0" | b"HTTP/1.1" => {}
+        [b'H', b'T', b'T', b'P', b'/', major, b'.', minor]
+            if major.is_ascii_digit() && minor.is_ascii_digit() =>
+        {
+            return Err(Error::UnsupportedVersion);
+        }
+        _ => return Err(Error::InvalidRequestLine),
+    }
+
+    let mut request = Request::new(method.to_string(), uri.to_string());
+
+    request.version = version.to_string();
+
+    Ok(request)
+}
+
+/// Reads header or trailer fields up to the empty line that ends them.
+fn receive_fields<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<HeaderMap, Error> {
+    let mut fields = HeaderMap::new();
+    let mut size = 0;
+
+    loop {
+        let line = read_head_line(
+            reader,
+            limits.max_header_size.saturating_sub(size),
+            Error::HeadersTooLarge,
+        )?;
+
+        if line.is_empty() {
+            return Ok(fields);
+        }
+
+        if fields.len() == limits.max_headers {
+            return Err(Error::HeadersTooLarge);
+        }
+
+        // Folding a value over several lines is obsolete, and RFC 9112 lets servers reject it.
+        if line.starts_with(b" ") || line.starts_with(b"\t") {
+            return Err(Error::InvalidRequestHeader);
+        }
+
+        let colon = line
+            .iter()
+            .position(|&c| c == b':')
+            .ok_or(Error::HeaderExpected)?;
+        let name = std::str::from_utf8(&line[..colon])
+            .ok()
+            .filter(|name| headers::is_token(name))
+            .ok_or(Error::InvalidRequestHeader)?;
+        let value = line[colon + 1..].trim_ascii();
+
+        if value.iter().any(|&c| c.is_ascii_control() && c != b'\t') {
+            return Err(Error::InvalidRequestHeader);
+        }
+
+        fields.append_unchecked(name, &String::from_utf8_lossy(value));
+        size += line.len() + 2;
+    }
+}
+
+/// Returns the `Content-Length` of a request, which must be a plain number. A list of equal
+/// values, as some proxies send, is accepted, but differing ones fail.
+fn content_length(headers: &HeaderMap) -> Result<Option<usize>, Error> {
+    let mut length = None;
+
+    for value in headers.get_all("content-length") {
+        for value in value.split(',').map(str::trim) {
+            if value.is_empty() || !value.bytes().all(|c| c.is_ascii_digit()) {
+                return Err(Error::InvalidContentLength);
+            }
+
+            let value = value.parse().map_err(|_| Error::InvalidContentLength)?;
+
+            if length.is_some_and(|length| length != value) {
+                return Err(Error::InvalidContentLength);
+            }
+
+            length = Some(value);
+        }
+    }
+
+    Ok(length)
+}
+
+/// Writes the body of a streamed response, produced piece by piece after the headers are sent.
+pub type Stream = Box<dyn FnOnce(&mut Chunks) -> Result<(), io::Error> + Send>;
+
+/// The body of a response: either bytes known up front, sent with `Content-Length`, or a stream
+/// sent with chunked transfer encoding.
+pub enum Body {
+    Bytes(Vec<u8>),
+    Stream(Stream),
+    /// The given number of bytes read from the current position of a file, so that large files
+    /// aren't loaded into memory.
+    File(std::fs::File, u64),
+    /// Left out, as in the answer to a `HEAD` request, while the headers still describe the
+    /// body: with its length if it's known, or as chunked otherwise.
+    Omitted(Option<u64>),
+}
+
+impl Body {
+    /// Returns the bytes of the body, or nothing for a stream or a file that hasn't been sent
+    /// yet.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Body::Bytes(bytes) => bytes,
+            Body::Stream(_) | Body::File(..) | Body::Omitted(_) => &[],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.as_bytes().is_empty()
+    }
+}
+
+impl Default for Body {
+    fn default() -> Body {
+        Body::Bytes(Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body::Bytes(bytes)
+    }
+}
+
+impl From<&[u8]> for Body {
+    fn from(bytes: &[u8]) -> Body {
+        Body::Bytes(bytes.to_vec())
+    }
+}
+
+impl From<String> for Body {
+    fn from(s: String) -> Body {
+        Body::Bytes(s.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(s: &str) -> Body {
+        Body::Bytes(s.as_bytes().to_vec())
+    }
+}
+
 pub struct Response {
     pub status: u16,
     pub status_text: String,
-    pub body: String,
-    pub headers: Headers,
+    pub body: Body,
+    pub headers: HeaderMap,
 }
 
 impl Response {
-    pub fn new(status: u16, status_text: String, body: String) -> Response {
+    /// Creates a response with the reason phrase of the status, which may be given as a
+    /// `StatusCode` or a plain number.
+    pub fn new<S: Into<StatusCode>, B: Into<Body>>(status: S, body: B) -> Response {
+        let status = status.into();
         let mut response = Response {
-            status,
-            status_text,
-            body,
-            headers: Headers::new(),
+            status: status.as_u16(),
+            status_text: status.reason().to_string(),
+            body: body.into(),
+            headers: HeaderMap::new(),
         };
 
-        response.header("Cache-Control".to_string(), "no-cache, private".to_string());
-        response.header("X-Powered-By".to_string(), "simplicity".to_string());
+        response
+            .headers
+            .insert_unchecked("Cache-Control", "no-cache, private");
+        response
+            .headers
+            .insert_unchecked("X-Powered-By", "simplicity");
 
         response
     }
 
-    pub fn new_from_str(status: u16, status_text: &str, body: &str) -> Response {
-        Response::new(status, status_text.to_string(), body.to_string())
+    pub fn not_found() -> Response {
+        let mut response = Response::new(StatusCode::NOT_FOUND, "Not Found");
+
+        response
+            .headers
+            .insert_unchecked("Content-Type", "text/plain; charset=UTF-8");
+
+        response
     }
 
-    pub fn not_found() -> Response {
-        let mut response = Response::new_from_str(404, "Not found", "Not found");
+    pub fn method_not_allowed(methods: &[String]) -> Response {
+        let mut response = Response::new(StatusCode::METHOD_NOT_ALLOWED, "Method Not Allowed");
 
-        response.header("Content-Type".to_string(), "text/plain; charset=UTF-8".to_string());
+        response
+            .headers
+            .insert_unchecked("Allow", &methods.join(", "));
+        response
+            .headers
+            .insert_unchecked("Content-Type", "text/plain; charset=UTF-8");
 
         response
     }
 
     pub fn plain_text(text: String) -> Response {
-        let mut response = Response::new(200, "OK".to_string(), text);
+        let mut response = Response::new(StatusCode::OK, text);
 
-        response.header("Content-Type".to_string(), "text/plain; charset=UTF-8".to_string());
+        response
+            .headers
+            .insert_unchecked("Content-Type", "text/plain; charset=UTF-8");
 
         response
     }
 
     pub fn html(html: String) -> Response {
-        let mut response = Response::new(200, "OK".to_string(), html);
+        let mut response = Response::new(StatusCode::OK, html);
 
-        response.header("Content-Type".to_string(), "text/html; charset=UTF-8".to_string());
+        response
+            .headers
+            .insert_unchecked("Content-Type", "text/html; charset=UTF-8");
 
         response
     }
 
     pub fn json(json: String) -> Response {
-        let mut response = Response::new(200, "OK".to_string(), json);
+        let mut response = Response::new(StatusCode::OK, json);
+
+        response
+            .headers
+            .insert_unchecked("Content-Type", "application/json");
+
+        response
+    }
 
-        response.header("Content-Type".to_string(), "application/json".to_string());
+    /// Creates a response whose body is written by `stream` using chunked transfer encoding, so
+    /// its length doesn't need to be known up front.
+    pub fn stream<F>(stream: F) -> Response
+    where
+        F: FnOnce(&mut Chunks) -> Result<(), io::Error> + Send + 'static,
+    {
+        Response::new(StatusCode::OK, Body::Stream(Box::new(stream)))
+    }
+
+    /// Serializes `value` into a JSON response.
+    #[cfg(feature = "serde")]
+    pub fn json_value<T: serde::Serialize + ?Sized>(value: &T) -> Result<Response, Error> {
+        Ok(Response::json(
+            serde_json::to_string(value).map_err(Error::SerializeJson)?,
+        ))
+    }
+
+    /// Creates a response carrying arbitrary bytes, such as an image or an archive.
+    pub fn bytes(content_type: &str, bytes: Vec<u8>) -> Response {
+        let mut response = Response::new(StatusCode::OK, bytes);
+
+        response
+            .headers
+            .insert_unchecked("Content-Type", content_type);
 
         response
     }
 
-    pub fn header(&mut self, name: String, value: String) {
-        self.headers.insert(name, value);
+    pub fn no_content() -> Response {
+        Response::new(StatusCode::NO_CONTENT, "")
     }
 
-    pub fn send(&self, mut stream: &std::net::TcpStream) -> Result<(), io::Error> {
+    /// Redirects to `location` with `302 Found`.
+    pub fn redirect(location: &str) -> Response {
+        Response::redirect_with(StatusCode::FOUND, location)
+    }
+
+    /// Redirects to `location` with `301 Moved Permanently`, which browsers remember.
+    pub fn redirect_permanent(location: &str) -> Response {
+        Response::redirect_with(StatusCode::MOVED_PERMANENTLY, location)
+    }
+
+    /// Redirects to `location` with `303 See Other`, which makes the browser follow it with a
+    /// `GET` whatever the method of the request, as after a form submission.
+    pub fn see_other(location: &str) -> Response {
+        Response::redirect_with(StatusCode::SEE_OTHER, location)
+    }
+
+    /// Redirects to the page the request came from, as told by its `Referer` header, or to `/`
+    /// without one.
+    pub fn back(request: &Request) -> Response {
+        Response::redirect(request.headers.get("Referer").unwrap_or("/"))
+    }
+
+    fn redirect_with(status: StatusCode, location: &str) -> Response {
+        let mut response = Response::new(status, "");
+
+        // Control characters are encoded rather than rejected, so that a location built from
+        // user input can't inject headers.
+        let location: String = location
+            .chars()
+            .map(|c| match c {
+                c if c.is_ascii_control() => format!("%{:02X}", c as u32),
+                c => c.to_string(),
+            })
+            .collect();
+
+        response.headers.insert_unchecked("Location", &location);
+
+        response
+    }
+
+    /// Sets a header, replacing its previous values. Fails with `Error::InvalidHeader` rather
+    /// than let a value containing a line break inject further headers.
+    pub fn header(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        self.headers.insert(name, value)
+    }
+
+    /// Adds a header value without replacing the previous ones.
+    pub fn append_header(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        self.headers.append(name, value)
+    }
+
+    /// Adds a `Set-Cookie` header. Fails with `Error::InvalidCookie` if the cookie's name or value
+    /// has characters cookies can't carry.
+    pub fn cookie(&mut self, cookie: Cookie) -> Result<(), Error> {
+        cookie.validate()?;
+
+        self.append_header("Set-Cookie", &cookie.to_string())
+    }
+
+    /// Drops the body, as the answer to a `HEAD` request must, but keeps describing it in the
+    /// headers.
+    pub fn omit_body(&mut self) {
+        self.body = match std::mem::take(&mut self.body) {
+            Body::Bytes(bytes) => Body::Omitted(Some(bytes.len() as u64)),
+            Body::File(_, length) => Body::Omitted(Some(length)),
+            Body::Stream(_) => Body::Omitted(None),
+            Body::Omitted(length) => Body::Omitted(length),
+        };
+    }
+
+    pub fn send<W: Write>(&mut self, stream: W) -> Result<(), io::Error> {
+        self.send_to(stream, "HTTP/1.1")
+    }
+
+    /// Sends the response to a client speaking `version`. HTTP/1.0 clients don't know chunked
+    /// encoding, so a streamed body is sent as it is, and the connection must be closed after it
+    /// to mark its end.
+    pub fn send_to<W: Write>(&mut self, mut stream: W, version: &str) -> Result<(), io::Error> {
+        let chunked = version != "HTTP/1.0";
         let mut s = String::new();
 
         s.push_str("HTTP/1.1 ");
         s.push_str(&self.status.to_string());
-        s.push_str(" ");
+        s.push(' ');
         s.push_str(&self.status_text);
         s.push_str("\r\n");
 
-        for (name, value) in &self.headers {
+        for (name, value) in self.headers.iter() {
+            // Framing follows from the body actually sent, whatever the handler says.
+            if name.eq_ignore_ascii_case("content-length")
+                || name.eq_ignore_ascii_case("transfer-encoding")
+            {
+                continue;
+            }
+
             s.push_str(name);
             s.push_str(": ");
             s.push_str(value);
             s.push_str("\r\n");
         }
 
-        s.push_str("\r\n");
-        s.push_str(&self.body);
-        s.push_str("\r\n");
-        
-        stream.write_all(s.as_bytes())?;
+        // Responses that never have a body neither describe nor send one, whatever the handler
+        // gave them, since the client wouldn't read it and would take it for the next response.
+        if self.status < 200 || self.status == 204 || self.status == 304 {
+            s.push_str("\r\n");
 
-        Ok(())
+            return stream.write_all(s.as_bytes());
+        }
+
+        match std::mem::take(&mut self.body) {
+            Body::Bytes(bytes) => {
+                s.push_str("Content-Length: ");
+                s.push_str(&bytes.len().to_string());
+                s.push_str("\r\n\r\n");
+
+                let mut output = s.into_bytes();
+                output.extend_from_slice(&bytes);
+                self.body = Body::Bytes(bytes);
+
+                stream.write_all(&output)
+            }
+            Body::Stream(body) => {
+                if chunked {
+                    s.push_str("Transfer-Encoding: chunked\r\n");
+                }
+                s.push_str("\r\n");
+                stream.write_all(s.as_bytes())?;
+
+                let mut chunks = Chunks {
+                    stream: &mut stream,
+                    chunked,
+                };
+                body(&mut chunks)?;
+
+                if !chunked {
+                    return stream.flush();
+                }
+
+                stream.write_all(b"0\r\n\r\n")
+            }
+            Body::File(file, length) => {
+                s.push_str("Content-Length: ");
+                s.push_str(&length.to_string());
+                s.push_str("\r\n\r\n");
+                stream.write_all(s.as_bytes())?;
+
+                let copied = io::copy(&mut file.take(length), &mut stream)?;
+
+                if copied < length {
+                    // The file shrank while it was sent, so the promised length can't be met.
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+
+                Ok(())
+            }
+            Body::Omitted(length) => {
+                match length {
+                    Some(length) => {
+                        s.push_str("Content-Length: ");
+                        s.push_str(&length.to_string());
+                        s.push_str("\r\n");
+                    }
+                    None if chunked => s.push_str("Transfer-Encoding: chunked\r\n"),
+                    None => {}
+                }
+                s.push_str("\r\n");
+                self.body = Body::Omitted(length);
+
+                stream.write_all(s.as_bytes())
+            }
+        }
+    }
+}
+
+/// Frames everything written to it as chunks of a `Transfer-Encoding: chunked` body.
+pub struct Chunks<'a> {
+    stream: &'a mut dyn Write,
+    /// Off for HTTP/1.0 clients, which get the body as it is.
+    chunked: bool,
+}
+
+impl Write for Chunks<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        // An empty chunk would terminate the body, so empty writes are dropped.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if !self.chunked {
+            self.stream.write_all(buf)?;
+
+            return Ok(buf.len());
+        }
+
+        write!(self.stream, "{:x}\r\n", buf.len())?;
+        self.stream.write_all(buf)?;
+        self.stream.write_all(b"\r\n")?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.stream.flush()
     }
 }
 
@@ -319,6 +1225,379 @@ impl Fake {
     }
 
     pub fn see(&self, s: &str) -> bool {
-        self.response.body.contains(s)
+        String::from_utf8_lossy(self.response.body.as_bytes()).contains(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{Error, ErrorStatus, Limits, Request, Response, StatusCode};
+    use std::io::Write;
+
+    #[test]
+    fn it_receives_body_by_content_length() {
+        let mut input = "POST /posts HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloGET".as_bytes();
+
+        let request = Request::receive(&mut input, &Limits::default()).unwrap();
+
+        assert_eq!(request.text().unwrap(), "hello");
+        assert_eq!(input, b"GET");
+    }
+
+    #[test]
+    fn it_rejects_bodies_over_the_limit() {
+        let mut input = "POST /posts HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello".as_bytes();
+        let limits = Limits {
+            max_body_size: 4,
+            ..Limits::default()
+        };
+
+        let result = Request::receive(&mut input, &limits);
+
+        assert!(matches!(result, Err(Error::BodyTooLarge)));
+    }
+
+    #[test]
+    fn it_rejects_ambiguous_content_lengths() {
+        for length in ["+5", "5 5", "0x5", "", "5, 6"] {
+            let input = format!("POST /posts HTTP/1.1\r\nContent-Length: {length}\r\n\r\nhello");
+
+            assert!(matches!(
+                Request::receive(&mut input.as_bytes(), &Limits::default()),
+                Err(Error::InvalidContentLength)
+            ));
+        }
+
+        let mut input =
+            "POST /posts HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nhello!"
+                .as_bytes();
+        assert!(matches!(
+            Request::receive(&mut input, &Limits::default()),
+            Err(Error::InvalidContentLength)
+        ));
+
+        let mut input = "POST /posts HTTP/1.1\r\nContent-Length: 5, 5\r\n\r\nhello".as_bytes();
+        let request = Request::receive(&mut input, &Limits::default()).unwrap();
+        assert_eq!(request.text().unwrap(), "hello");
+    }
+
+    #[test]
+    fn it_decodes_compressed_bodies() {
+        use flate2::write::GzEncoder;
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&[b'a'; 1000]).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        let receive = |encoding: &str, limits: &Limits| {
+            let mut input = format!(
+                "POST /batch HTTP/1.1\r\nContent-Encoding: {encoding}\r\nContent-Length: {}\r\n\r\n",
+                gzipped.len()
+            )
+            .into_bytes();
+            input.extend_from_slice(&gzipped);
+
+            Request::receive(&mut input.as_slice(), limits)
+        };
+
+        let request = receive("gzip", &Limits::default()).unwrap();
+        assert_eq!(request.body, vec![b'a'; 1000]);
+        assert_eq!(request.headers.get("content-encoding"), None);
+
+        let limits = Limits {
+            max_decoded_body_size: 999,
+            ..Limits::default()
+        };
+        assert!(matches!(receive("gzip", &limits), Err(Error::BodyTooLarge)));
+        assert!(matches!(
+            receive("br", &Limits::default()),
+            Err(Error::UnsupportedMediaType)
+        ));
+        assert!(matches!(
+            receive("deflate", &Limits::default()),
+            Err(Error::InvalidEncoding)
+        ));
+
+        // Repeated headers all count.
+        assert!(matches!(
+            receive("identity\r\nContent-Encoding: br", &Limits::default()),
+            Err(Error::UnsupportedMediaType)
+        ));
+        let request = receive("identity\r\nContent-Encoding: gzip", &Limits::default()).unwrap();
+        assert_eq!(request.body, vec![b'a'; 1000]);
+    }
+
+    #[test]
+    fn it_parses_request_heads_strictly() {
+        let receive = |head: &str| Request::receive(&mut head.as_bytes(), &Limits::default());
+
+        let request = receive("\r\nGET /posts HTTP/1.0\nHost:  localhost \r\n\r\n").unwrap();
+        assert_eq!(request.version, "HTTP/1.0");
+        assert_eq!(request.headers.get("host"), Some("localhost"));
+
+        assert!(matches!(
+            receive("GET /posts\r\n\r\n"),
+            Err(Error::InvalidRequestLine)
+        ));
+        assert!(matches!(
+            receive("GET  /posts HTTP/1.1\r\n\r\n"),
+            Err(Error::UriExpected)
+        ));
+        assert!(matches!(
+            receive("GET /posts HTTP/2.0\r\n\r\n"),
+            Err(Error::UnsupportedVersion)
+        ));
+        assert!(matches!(
+            receive("GET / HTTP/1.1\r\nX-Long: a\r\n b\r\n\r\n"),
+            Err(Error::InvalidRequestHeader)
+        ));
+        assert!(matches!(
+            receive("GET / HTTP/1.1\r\nHost : localhost\r\n\r\n"),
+            Err(Error::InvalidRequestHeader)
+        ));
+        assert!(matches!(
+            receive("GET / HTTP/1.1\r\nHost\r\n\r\n"),
+            Err(Error::HeaderExpected)
+        ));
+    }
+
+    #[test]
+    fn it_limits_request_heads() {
+        let limits = Limits {
+            max_request_line_size: 16,
+            max_header_size: 32,
+            max_headers: 2,
+            ..Limits::default()
+        };
+        let receive = |head: &str| Request::receive(&mut head.as_bytes(), &limits);
+
+        assert!(receive("GET /a HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n").is_ok());
+
+        let Err(err) = receive("GET /posts/1 HTTP/1.1\r\n\r\n") else {
+            panic!("expected the request line to be too long");
+        };
+        assert_eq!(err.status(), StatusCode::URI_TOO_LONG);
+
+        let Err(err) = receive("GET /a HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n") else {
+            panic!("expected too many headers");
+        };
+        assert_eq!(err.status(), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+
+        let head = format!("GET /a HTTP/1.1\r\nA: {}\r\n\r\n", "x".repeat(40));
+        assert!(matches!(receive(&head), Err(Error::HeadersTooLarge)));
+    }
+
+    #[test]
+    fn it_matches_routes_ignoring_query_string() {
+        let mut request = Request::new("GET".to_string(), "/hello/big%20world?x=1".to_string());
+
+        assert!(request.is("GET /hello/{name}"));
+        assert_eq!(request.parameters.get("name").unwrap(), "big world");
+    }
+
+    #[test]
+    fn it_parses_query_strings_with_repeated_keys() {
+        let request = Request::new(
+            "GET".to_string(),
+            "/posts?tag=a&tag=b%2Bc&q=hello+world".to_string(),
+        );
+
+        let query = request.query();
+
+        assert_eq!(query.get("tag"), Some("a"));
+        assert_eq!(query.get_all("tag"), vec!["a", "b+c"]);
+        assert_eq!(query.get("q"), Some("hello world"));
+        assert_eq!(query.get("missing"), None);
+    }
+
+    #[test]
+    fn it_receives_chunked_body_with_trailers() {
+        let mut input = "POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\nChecksum: abc\r\n\r\n"
+            .as_bytes();
+
+        let request = Request::receive(&mut input, &Limits::default()).unwrap();
+
+        assert_eq!(request.text().unwrap(), "hello, world");
+        assert_eq!(request.trailers.get("checksum").unwrap(), "abc");
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn it_limits_chunk_size_lines() {
+        let input = format!(
+            "POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;{}\r\nhello\r\n0\r\n\r\n",
+            "x".repeat(2048)
+        );
+        assert!(matches!(
+            Request::receive(&mut input.as_bytes(), &Limits::default()),
+            Err(Error::InvalidChunk)
+        ));
+
+        let mut input = "POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello!!\r\n0\r\n\r\n"
+            .as_bytes();
+        assert!(matches!(
+            Request::receive(&mut input, &Limits::default()),
+            Err(Error::InvalidChunk)
+        ));
+    }
+
+    #[test]
+    fn it_frames_responses_with_content_length() {
+        let mut response = Response::plain_text("hello".to_string());
+        let mut output = Vec::new();
+
+        response.send(&mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("\r\nContent-Length: 5\r\n"));
+        assert!(output.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn it_sends_binary_bodies() {
+        let png = vec![0x89, b'P', b'N', b'G', 0xff, 0x00];
+        let mut response = Response::bytes("image/png", png.clone());
+        let mut output = Vec::new();
+
+        response.send(&mut output).unwrap();
+
+        assert!(output.ends_with(b"Content-Length: 6\r\n\r\n\x89PNG\xff\x00"));
+        assert_eq!(response.body.as_bytes(), png);
+    }
+
+    #[test]
+    fn it_keeps_http_1_1_connections_alive_unless_closed() {
+        let mut request = Request::new("GET".to_string(), "/".to_string());
+        assert!(request.keep_alive());
+
+        request.headers.insert("Connection", "close").unwrap();
+        assert!(!request.keep_alive());
+
+        request.version = "HTTP/1.0".to_string();
+        request.headers.remove("Connection");
+        assert!(!request.keep_alive());
+
+        request.headers.insert("Connection", "Keep-Alive").unwrap();
+        assert!(request.keep_alive());
+    }
+
+    #[test]
+    fn it_redirects() {
+        let mut request = Request::new("POST".to_string(), "/posts".to_string());
+
+        let response = Response::back(&request);
+        assert_eq!(
+            (response.status, response.status_text.as_str()),
+            (302, "Found")
+        );
+        assert_eq!(response.headers.get("Location"), Some("/"));
+
+        request
+            .headers
+            .insert("Referer", "http://localhost/posts/new")
+            .unwrap();
+        let response = Response::back(&request);
+        assert_eq!(
+            response.headers.get("Location"),
+            Some("http://localhost/posts/new")
+        );
+
+        let response = Response::see_other("/posts?x=\r\nSet-Cookie: a=b");
+        assert_eq!(response.status_text, "See Other");
+        assert_eq!(
+            response.headers.get("Location"),
+            Some("/posts?x=%0D%0ASet-Cookie: a=b")
+        );
+    }
+
+    #[test]
+    fn it_streams_chunked_responses() {
+        let mut response = Response::stream(|chunks| {
+            chunks.write_all(b"hello")?;
+            chunks.write_all(b", world")
+        });
+        let mut output = Vec::new();
+
+        response.send(&mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(output.ends_with("\r\n\r\n5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn it_sends_no_body_with_bodyless_statuses() {
+        let mut stream = Response::stream(|chunks| chunks.write_all(b"oops"));
+        stream.status = 304;
+
+        for mut response in [Response::new(204u16, "oops"), stream] {
+            let mut output = Vec::new();
+
+            response.send(&mut output).unwrap();
+
+            let output = String::from_utf8(output).unwrap();
+            assert!(output.ends_with("\r\n\r\n"));
+            assert!(!output.contains("oops"));
+            assert!(!output.contains("Transfer-Encoding"));
+        }
+    }
+
+    #[test]
+    fn it_streams_unframed_responses_to_http_1_0_clients() {
+        let mut response = Response::stream(|chunks| chunks.write_all(b"hello"));
+        let mut output = Vec::new();
+
+        response.send_to(&mut output, "HTTP/1.0").unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(!output.contains("Transfer-Encoding"));
+        assert!(output.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn it_rejects_transfer_codings_other_than_chunked() {
+        let mut input = "POST /upload HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n\
+            5\r\nhello\r\n0\r\n\r\n"
+            .as_bytes();
+
+        assert!(matches!(
+            Request::receive(&mut input, &Limits::default()),
+            Err(Error::UnsupportedTransferEncoding)
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn it_maps_json_body_errors() {
+        use std::collections::HashMap;
+
+        let mut request = Request::new("POST".to_string(), "/posts".to_string());
+        request.body = br#"{"likes":3}"#.to_vec();
+
+        let err = request.json::<HashMap<String, u32>>().unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        request
+            .headers
+            .insert("Content-Type", "application/json; charset=utf-8")
+            .unwrap();
+        let likes = request.json::<HashMap<String, u32>>().unwrap();
+        assert_eq!(likes["likes"], 3);
+
+        request.body = br#"{"likes":"many"}"#.to_vec();
+        let err = request.json::<HashMap<String, u32>>().unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNPROCESSABLE_CONTENT);
+
+        request.body = br#"{"likes":"#.to_vec();
+        let err = request.json::<HashMap<String, u32>>().unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+        let response = Response::json_value(&likes).unwrap();
+        assert_eq!(response.body.as_bytes(), br#"{"likes":3}"#);
+        assert_eq!(
+            response.headers.get("Content-Type"),
+            Some("application/json")
+        );
     }
 }
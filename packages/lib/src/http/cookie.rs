@@ -0,0 +1,287 @@
+use crate::crypt::Keys;
+use crate::http::{date, headers, Error};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Cookies sent by the client, by name.
+pub type Cookies = HashMap<String, String>;
+
+/// Parses a `Cookie` request header. When a name repeats, the first value is kept, as browsers
+/// send the cookie with the most specific path first.
+pub fn parse(header: &str) -> Cookies {
+    let mut cookies = Cookies::new();
+
+    for pair in header.split(';') {
+        let Some((name, value)) = pair.split_once('=') else {
+            continue;
+        };
+        let name = name.trim();
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+
+        if !name.is_empty() {
+            cookies
+                .entry(name.to_string())
+                .or_insert_with(|| value.to_string());
+        }
+    }
+
+    cookies
+}
+
+/// Returns the value of a signed or encrypted cookie once it's verified to belong to `name`.
+pub(crate) fn unseal(name: &str, sealed: Option<String>) -> Option<String> {
+    sealed?
+        .strip_prefix(&format!("{name}="))
+        .map(|value| value.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sends the cookie with cross-site requests too. Browsers only accept it on `Secure`
+    /// cookies.
+    None,
+}
+
+/// A cookie to set with a `Set-Cookie` response header, built up attribute by attribute, as in
+/// `Cookie::new("theme", "dark").path("/").http_only(true)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub max_age: Option<Duration>,
+    pub expires: Option<SystemTime>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    pub fn new(name: &str, value: &str) -> Cookie {
+        Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Creates a cookie whose value the client can read but can't change without
+    /// `Request::signed_cookie` noticing. The signature covers the name too, so the value can't be
+    /// replayed under another cookie.
+    pub fn signed(name: &str, value: &str, keys: &Keys) -> Cookie {
+        Cookie::new(name, &keys.sign(&format!("{name}={value}")))
+    }
+
+    /// Creates a cookie whose value the client can neither read nor change. Read it back with
+    /// `Request::encrypted_cookie`.
+    pub fn encrypted(name: &str, value: &str, keys: &Keys) -> Cookie {
+        Cookie::new(name, &keys.encrypt(&format!("{name}={value}")))
+    }
+
+    /// Creates a cookie that makes the browser delete the cookie of the given name. Its path and
+    /// domain must match those the cookie was set with.
+    pub fn forget(name: &str) -> Cookie {
+        Cookie::new(name, "")
+            .max_age(Duration::ZERO)
+            .expires(UNIX_EPOCH)
+    }
+
+    pub fn path(mut self, path: &str) -> Cookie {
+        self.path = Some(path.to_string());
+
+        self
+    }
+
+    pub fn domain(mut self, domain: &str) -> Cookie {
+        self.domain = Some(domain.to_string());
+
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Cookie {
+        self.max_age = Some(max_age);
+
+        self
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Cookie {
+        self.expires = Some(expires);
+
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Cookie {
+        self.secure = secure;
+
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Cookie {
+        self.http_only = http_only;
+
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Cookie {
+        self.same_site = Some(same_site);
+
+        self
+    }
+
+    /// Checks that the name is a token, that the value only has the characters RFC 6265 allows,
+    /// and that no attribute contains `;`, so that none of them can add attributes of its own.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let attribute = |value: &Option<String>| {
+            value
+                .as_deref()
+                .is_none_or(|v| !v.bytes().any(|c| c == b';' || c.is_ascii_control()))
+        };
+
+        if !headers::is_token(&self.name)
+            || !self.value.bytes().all(is_cookie_octet)
+            || !attribute(&self.path)
+            || !attribute(&self.domain)
+        {
+            return Err(Error::InvalidCookie);
+        }
+
+        Ok(())
+    }
+}
+
+/// Tells whether `c` may appear in a cookie value: any visible ASCII character but `"`, `,`, `;`
+/// and `\`.
+fn is_cookie_octet(c: u8) -> bool {
+    matches!(c, 0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e)
+}
+
+/// Formats the cookie as the value of a `Set-Cookie` header.
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", date::format(expires))?;
+        }
+
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+
+        match self.same_site {
+            Some(SameSite::Strict) => write!(f, "; SameSite=Strict"),
+            Some(SameSite::Lax) => write!(f, "; SameSite=Lax"),
+            Some(SameSite::None) => write!(f, "; SameSite=None"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypt::Keys;
+    use crate::http::{Cookie, Request, Response, SameSite};
+    use std::time::Duration;
+
+    #[test]
+    fn it_parses_request_cookies() {
+        let mut request = Request::new("GET".to_string(), "/".to_string());
+        request
+            .headers
+            .insert("Cookie", "session=abc; theme=\"dark\"; session=older")
+            .unwrap();
+
+        assert_eq!(request.cookie("session").unwrap(), "abc");
+        assert_eq!(request.cookie("theme").unwrap(), "dark");
+        assert_eq!(request.cookie("missing"), None);
+    }
+
+    #[test]
+    fn it_sets_several_cookies() {
+        let mut response = Response::plain_text("ok".to_string());
+        let mut output = Vec::new();
+
+        response
+            .cookie(
+                Cookie::new("session", "abc")
+                    .path("/")
+                    .max_age(Duration::from_secs(7200))
+                    .secure(true)
+                    .http_only(true)
+                    .same_site(SameSite::Strict),
+            )
+            .unwrap();
+        response.cookie(Cookie::forget("theme")).unwrap();
+        response.send(&mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(
+            "Set-Cookie: session=abc; Path=/; Max-Age=7200; Secure; HttpOnly; SameSite=Strict\r\n"
+        ));
+        assert!(output
+            .contains("Set-Cookie: theme=; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT\r\n"));
+    }
+
+    #[test]
+    fn it_reads_back_signed_and_encrypted_cookies() {
+        let keys = Keys::new(b"secret");
+        let signed = Cookie::signed("remember", "42", &keys);
+        let encrypted = Cookie::encrypted("flash", "saved", &keys);
+        let mut request = Request::new("GET".to_string(), "/".to_string());
+        let header = format!(
+            "remember={}; flash={}; copy={}",
+            signed.value, encrypted.value, signed.value
+        );
+        request.headers.insert("Cookie", &header).unwrap();
+
+        assert_eq!(request.signed_cookie("remember", &keys).unwrap(), "42");
+        assert_eq!(request.encrypted_cookie("flash", &keys).unwrap(), "saved");
+        assert_eq!(request.signed_cookie("copy", &keys), None);
+        assert_eq!(request.signed_cookie("flash", &keys), None);
+    }
+
+    #[test]
+    fn it_rejects_cookies_that_would_add_attributes() {
+        let mut response = Response::plain_text("ok".to_string());
+
+        assert!(response
+            .cookie(Cookie::new("theme", "x; Domain=evil.com"))
+            .is_err());
+        assert!(response.cookie(Cookie::new("the me", "dark")).is_err());
+        assert!(response
+            .cookie(Cookie::new("theme", "dark").path("/; Secure"))
+            .is_err());
+        assert!(response.cookie(Cookie::new("theme", "dark")).is_ok());
+        assert_eq!(response.headers.get_all("Set-Cookie"), vec!["theme=dark"]);
+    }
+}
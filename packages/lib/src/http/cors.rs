@@ -0,0 +1,239 @@
+use crate::http::{Middleware, Next, Request, Response};
+use std::time::Duration;
+
+/// Lets pages from other origins call the app, by answering CORS preflight requests and adding
+/// `Access-Control-Allow-*` headers to the responses of allowed origins.
+///
+/// Requests from other origins pass through without these headers, so the browser keeps them from
+/// reading the response. Add it to the router rather than to routes, so that it sees preflight requests
+/// for any path.
+pub struct Cors {
+    origins: Vec<String>,
+    methods: Vec<String>,
+    headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    /// Allows no origin until some are added with `origin`.
+    pub fn new() -> Cors {
+        Cors {
+            origins: Vec::new(),
+            methods: ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"]
+                .map(String::from)
+                .to_vec(),
+            headers: vec!["*".to_string()],
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allows an origin such as `http://localhost:5173`, or any origin with `*`.
+    pub fn origin(mut self, origin: &str) -> Cors {
+        self.origins.push(origin.trim_end_matches('/').to_string());
+
+        self
+    }
+
+    pub fn methods(mut self, methods: &[&str]) -> Cors {
+        self.methods = methods.iter().map(|m| m.to_uppercase()).collect();
+
+        self
+    }
+
+    /// Sets the request headers other origins may send. `*`, the default, allows any.
+    pub fn headers(mut self, headers: &[&str]) -> Cors {
+        self.headers = headers.iter().map(|h| h.to_string()).collect();
+
+        self
+    }
+
+    /// Lets other origins send cookies and read responses to requests made with them.
+    pub fn credentials(mut self, credentials: bool) -> Cors {
+        self.credentials = credentials;
+
+        self
+    }
+
+    /// Lets browsers cache preflight responses for the given time.
+    pub fn max_age(mut self, max_age: Duration) -> Cors {
+        self.max_age = Some(max_age);
+
+        self
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.origins.iter().any(|o| o == "*" || o == origin)
+    }
+
+    /// Tells whether all origins are answered with `*`. Browsers don't accept it for requests
+    /// with credentials, so the origin is echoed then.
+    fn allows_any(&self) -> bool {
+        !self.credentials && self.origins.iter().any(|o| o == "*")
+    }
+
+    fn allow_origin(&self, origin: &str, response: &mut Response) {
+        response.headers.insert_unchecked(
+            "Access-Control-Allow-Origin",
+            if self.allows_any() { "*" } else { origin },
+        );
+
+        if self.credentials {
+            response
+                .headers
+                .insert_unchecked("Access-Control-Allow-Credentials", "true");
+        }
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Cors {
+        Cors::new()
+    }
+}
+
+impl<E> Middleware<E> for Cors {
+    fn handle(&self, request: &mut Request, next: Next<E>) -> Result<Response, E> {
+        let mut response = self.respond(request, next)?;
+
+        // Unless every origin gets `*`, caches must keep the answers to each origin apart,
+        // including those without CORS headers.
+        if !self.allows_any() {
+            response.headers.append_unchecked("Vary", "Origin");
+        }
+
+        Ok(response)
+    }
+}
+
+impl Cors {
+    fn respond<E>(&self, request: &mut Request, next: Next<E>) -> Result<Response, E> {
+        let Some(origin) = request.headers.get("Origin").map(str::to_string) else {
+            return next.run(request);
+        };
+
+        if !self.allows(&origin) {
+            return next.run(request);
+        }
+
+        let preflight = request.method == "OPTIONS"
+            && request
+                .headers
+                .contains_key("Access-Control-Request-Method");
+
+        if !preflight {
+            let mut response = next.run(request)?;
+
+            self.allow_origin(&origin, &mut response);
+
+            return Ok(response);
+        }
+
+        let mut response = Response::no_content();
+
+        self.allow_origin(&origin, &mut response);
+        response
+            .headers
+            .insert_unchecked("Access-Control-Allow-Methods", &self.methods.join(", "));
+
+        let headers = match request.headers.get("Access-Control-Request-Headers") {
+            Some(requested) if self.headers.iter().any(|h| h == "*") => requested.to_string(),
+            _ => self.headers.join(", "),
+        };
+
+        if !headers.is_empty() {
+            response
+                .headers
+                .insert_unchecked("Access-Control-Allow-Headers", &headers);
+        }
+
+        if let Some(max_age) = self.max_age {
+            response
+                .headers
+                .insert_unchecked("Access-Control-Max-Age", &max_age.as_secs().to_string());
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{Cors, Request, Response, Router};
+    use std::time::Duration;
+
+    fn handle(method: &str, origin: &str, headers: &[(&str, &str)]) -> Response {
+        let mut router: Router<()> = Router::new();
+
+        router.middleware(
+            Cors::new()
+                .origin("http://localhost:5173")
+                .credentials(true)
+                .max_age(Duration::from_secs(600)),
+        );
+        router.get("/api/posts", |_| {
+            Ok(Response::plain_text("posts".to_string()))
+        });
+
+        let mut request = Request::new(method.to_string(), "/api/posts".to_string());
+        request.headers.insert("Origin", origin).unwrap();
+        for (name, value) in headers {
+            request.headers.insert(name, value).unwrap();
+        }
+
+        router.handle(&mut request).unwrap()
+    }
+
+    #[test]
+    fn it_answers_preflight_requests_of_allowed_origins() {
+        let response = handle(
+            "OPTIONS",
+            "http://localhost:5173",
+            &[
+                ("Access-Control-Request-Method", "POST"),
+                (
+                    "Access-Control-Request-Headers",
+                    "content-type, x-xsrf-token",
+                ),
+            ],
+        );
+
+        assert_eq!(response.status, 204);
+        let header = |name| response.headers.get(name).unwrap();
+        assert_eq!(
+            header("Access-Control-Allow-Origin"),
+            "http://localhost:5173"
+        );
+        assert_eq!(header("Access-Control-Allow-Credentials"), "true");
+        assert_eq!(
+            header("Access-Control-Allow-Headers"),
+            "content-type, x-xsrf-token"
+        );
+        assert_eq!(header("Access-Control-Max-Age"), "600");
+    }
+
+    #[test]
+    fn it_leaves_other_origins_alone() {
+        let response = handle("GET", "http://localhost:5173", &[]);
+        assert_eq!(response.body.as_bytes(), b"posts");
+        assert_eq!(
+            response.headers.get("Access-Control-Allow-Origin"),
+            Some("http://localhost:5173")
+        );
+        assert_eq!(response.headers.get("Vary"), Some("Origin"));
+
+        let response = handle(
+            "OPTIONS",
+            "http://evil.example",
+            &[("Access-Control-Request-Method", "POST")],
+        );
+        assert_eq!(response.status, 204);
+        assert_eq!(response.headers.get("Access-Control-Allow-Origin"), None);
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, OPTIONS"));
+
+        let response = handle("GET", "http://evil.example", &[]);
+        assert_eq!(response.headers.get("Access-Control-Allow-Origin"), None);
+        assert_eq!(response.headers.get("Vary"), Some("Origin"));
+    }
+}
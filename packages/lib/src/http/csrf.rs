@@ -0,0 +1,235 @@
+use crate::crypt::Keys;
+use crate::http::{
+    cookie, Cookie, Error, Middleware, Next, Request, Response, SameSite, StatusCode,
+};
+
+/// Rejects state-changing requests that don't carry the session's CSRF token, answering them
+/// with `419 Page Expired`. Must run after `Sessions`, and fails with `Error::SessionExpected`
+/// otherwise.
+///
+/// The token is accepted from the `_token` form field, the `X-CSRF-TOKEN` header, or the
+/// `X-XSRF-TOKEN` header, which holds the encrypted `XSRF-TOKEN` cookie this middleware sets on
+/// every response. Axios, and so Inertia, sends the latter on its own.
+pub struct Csrf {
+    keys: Keys,
+    except: Vec<String>,
+}
+
+impl Csrf {
+    pub fn new(keys: Keys) -> Csrf {
+        Csrf {
+            keys,
+            except: Vec::new(),
+        }
+    }
+
+    /// Skips the check for the given path, or for all paths under it if it ends with `*`, as in
+    /// `/webhooks/*`.
+    pub fn except(mut self, path: &str) -> Csrf {
+        self.except.push(path.to_string());
+
+        self
+    }
+
+    fn is_exempt(&self, request: &Request) -> bool {
+        let path = request.path();
+
+        matches!(
+            request.method.as_str(),
+            "GET" | "HEAD" | "OPTIONS" | "TRACE"
+        ) || self
+            .except
+            .iter()
+            .any(|except| match except.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => path == except,
+            })
+    }
+
+    fn provided_token(&self, request: &Request) -> Option<String> {
+        if let Some(token) = request.form().get("_token") {
+            return Some(token.to_string());
+        }
+
+        if let Ok(Some(token)) = request.multipart().and_then(|m| m.text_field("_token")) {
+            return Some(token);
+        }
+
+        if let Some(token) = request.headers.get("X-CSRF-TOKEN") {
+            return Some(token.to_string());
+        }
+
+        let encrypted = request.headers.get("X-XSRF-TOKEN")?;
+
+        cookie::unseal("XSRF-TOKEN", self.keys.decrypt(encrypted))
+    }
+}
+
+impl<E: From<Error>> Middleware<E> for Csrf {
+    fn handle(&self, request: &mut Request, next: Next<E>) -> Result<Response, E> {
+        let token = session(request)?.token();
+
+        if !self.is_exempt(request) {
+            let provided = self.provided_token(request).unwrap_or_default();
+
+            if !constant_time_eq(provided.as_bytes(), token.as_bytes()) {
+                let mut response = Response::new(StatusCode::PAGE_EXPIRED, "Page Expired");
+
+                response
+                    .headers
+                    .insert_unchecked("Content-Type", "text/plain; charset=UTF-8");
+
+                return Ok(response);
+            }
+        }
+
+        let mut response = next.run(request)?;
+
+        // The handler may have regenerated the token, as on login.
+        let token = session(request)?.token();
+
+        response.cookie(
+            Cookie::encrypted("XSRF-TOKEN", &token, &self.keys)
+                .path("/")
+                .same_site(SameSite::Lax),
+        )?;
+
+        Ok(response)
+    }
+}
+
+fn session(request: &mut Request) -> Result<&mut crate::http::Session, Error> {
+    request.session.as_mut().ok_or(Error::SessionExpected)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypt::Keys;
+    use crate::http::{Csrf, Error, FileStore, Request, Response, Router, Sessions};
+
+    fn router(dir: &std::path::Path) -> Router<Error> {
+        let keys = Keys::new(b"secret");
+        let mut router: Router<Error> = Router::new();
+
+        router
+            .middleware(Sessions::new(FileStore::new(dir), keys.clone()))
+            .middleware(Csrf::new(keys).except("/webhooks/*"));
+        router.get("/", |request| {
+            let token = request.session.as_mut().unwrap().token();
+
+            Ok(Response::plain_text(token))
+        });
+        router.post("/posts", |_| Ok(Response::plain_text("saved".to_string())));
+        router.post("/webhooks/github", |_| {
+            Ok(Response::plain_text("received".to_string()))
+        });
+
+        router
+    }
+
+    fn post(router: &Router<Error>, uri: &str, headers: &[(&str, &str)], body: &str) -> u16 {
+        let mut request = Request::new("POST".to_string(), uri.to_string());
+
+        for (name, value) in headers {
+            request.headers.insert(name, value).unwrap();
+        }
+        request.body = body.as_bytes().to_vec();
+
+        router.handle(&mut request).unwrap().status
+    }
+
+    #[test]
+    fn it_fails_without_sessions() {
+        let mut router: Router<Error> = Router::new();
+
+        router.middleware(Csrf::new(Keys::new(b"secret")));
+        router.get("/", |_| Ok(Response::plain_text("home".to_string())));
+
+        let result = router.handle(&mut Request::new("GET".to_string(), "/".to_string()));
+        assert!(matches!(result, Err(Error::SessionExpected)));
+    }
+
+    #[test]
+    fn it_checks_tokens_of_unsafe_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let router = router(dir.path());
+        let mut request = Request::new("GET".to_string(), "/".to_string());
+        let response = router.handle(&mut request).unwrap();
+        let token = String::from_utf8(response.body.as_bytes().to_vec()).unwrap();
+        let cookie = |name: &str| {
+            response
+                .headers
+                .get_all("Set-Cookie")
+                .into_iter()
+                .find(|c| c.starts_with(name))
+                .and_then(|c| c.split(';').next())
+                .unwrap()
+                .to_string()
+        };
+        let session = &cookie("session=");
+        let xsrf = cookie("XSRF-TOKEN=");
+        let xsrf_value = xsrf.strip_prefix("XSRF-TOKEN=").unwrap();
+        let form = "application/x-www-form-urlencoded";
+
+        assert_eq!(post(&router, "/posts", &[("Cookie", session)], ""), 419);
+        assert_eq!(
+            post(
+                &router,
+                "/posts",
+                &[("Cookie", session), ("Content-Type", form)],
+                &format!("title=Hi&_token={token}")
+            ),
+            200
+        );
+        assert_eq!(
+            post(
+                &router,
+                "/posts",
+                &[("Cookie", session), ("X-CSRF-TOKEN", &token)],
+                ""
+            ),
+            200
+        );
+        assert_eq!(
+            post(
+                &router,
+                "/posts",
+                &[("Cookie", session), ("X-XSRF-TOKEN", xsrf_value)],
+                ""
+            ),
+            200
+        );
+        assert_eq!(
+            post(
+                &router,
+                "/posts",
+                &[("Cookie", session), ("X-CSRF-TOKEN", "forged")],
+                ""
+            ),
+            419
+        );
+        assert_eq!(
+            post(
+                &router,
+                "/posts",
+                &[
+                    ("Cookie", session),
+                    ("Content-Type", "multipart/form-data; boundary=XyZ")
+                ],
+                &format!(
+                    "--XyZ\r\nContent-Disposition: form-data; name=\"video\"; \
+                     filename=\"big.mp4\"\r\n\r\n{}\r\n--XyZ\r\n\
+                     Content-Disposition: form-data; name=\"_token\"\r\n\r\n\
+                     {token}\r\n--XyZ--\r\n",
+                    "x".repeat(3 * 1024 * 1024)
+                )
+            ),
+            200
+        );
+        assert_eq!(post(&router, "/webhooks/github", &[], ""), 200);
+    }
+}
@@ -0,0 +1,106 @@
+//! Dates in the IMF-fixdate format HTTP uses, such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+pub fn format(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let days = (secs / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Parses an IMF-fixdate. The obsolete RFC 850 and asctime formats aren't accepted.
+pub fn parse(s: &str) -> Option<SystemTime> {
+    let mut parts = s.split_whitespace();
+
+    parts.next().filter(|weekday| weekday.ends_with(','))?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|&m| m == month)? as i64 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts
+        .next()?
+        .split(':')
+        .map(|part| part.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+
+    if parts.next() != Some("GMT") || parts.next().is_some() || time.next().is_some() {
+        return None;
+    }
+
+    // Bounding the year keeps a date from a client from overflowing the arithmetic below.
+    if !(1..=9999).contains(&year)
+        || !(1..=31).contains(&day)
+        || hours > 23
+        || minutes > 59
+        || seconds > 60
+    {
+        return None;
+    }
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days
+        .checked_mul(86400)?
+        .checked_add(hours * 3600 + minutes * 60 + seconds)?;
+
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+// Conversions between days since the epoch and calendar dates, after Howard Hinnant's
+// `chrono`-compatible algorithms.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::date;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn it_formats_and_parses_http_dates() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+
+        assert_eq!(date::format(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(date::parse("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+        assert_eq!(date::parse("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(date::parse("Sun, 06 Nov 300000000000 08:49:37 GMT"), None);
+        assert_eq!(date::parse("Sun, 06 Nov 10000 08:49:37 GMT"), None);
+    }
+}
@@ -0,0 +1,450 @@
+use crate::http::compression::negotiate;
+use crate::http::{date, percent_decode, Body, Request, Response, StatusCode};
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Serves the files of a directory under a URL prefix, for example `public/` under `/assets`.
+///
+/// Requests can't reach outside of the directory, neither with `..` nor through symlinks.
+/// Responses carry `ETag` and `Last-Modified` so that browsers can revalidate with a `304`, and
+/// single byte ranges are honoured so that media can be seeked without downloading it whole.
+pub struct StaticFiles {
+    prefix: String,
+    root: PathBuf,
+    max_age: Option<Duration>,
+    precompressed: bool,
+}
+
+impl StaticFiles {
+    pub fn new<P: Into<PathBuf>>(prefix: &str, root: P) -> StaticFiles {
+        StaticFiles {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            root: root.into(),
+            max_age: None,
+            precompressed: false,
+        }
+    }
+
+    /// Lets browsers use their copy for `max_age` without revalidating, which suits file names
+    /// that change with their contents. Otherwise every use is revalidated.
+    pub fn max_age(mut self, max_age: Duration) -> StaticFiles {
+        self.max_age = Some(max_age);
+
+        self
+    }
+
+    /// Serves `app.js.br` or `app.js.gz`, when they sit next to `app.js`, to clients that accept
+    /// that encoding, as build tools can compress assets once rather than on every request.
+    pub fn precompressed(mut self, precompressed: bool) -> StaticFiles {
+        self.precompressed = precompressed;
+
+        self
+    }
+
+    pub(crate) fn with_prefix(mut self, prefix: &str) -> StaticFiles {
+        self.prefix = prefix.trim_end_matches('/').to_string();
+
+        self
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn handle(&self, request: &Request) -> Response {
+        let path = match request.path().strip_prefix(&self.prefix) {
+            Some(path) if path.is_empty() || path.starts_with('/') => percent_decode(path),
+            _ => return Response::not_found(),
+        };
+
+        let Some(path) = self.resolve(&path) else {
+            return Response::not_found();
+        };
+
+        if !self.precompressed {
+            return self.respond(request, &path, None);
+        }
+
+        let encoded = self.encoded(request, &path);
+        let mut response = self.respond(request, &path, encoded);
+
+        // The file sent depends on `Accept-Encoding`, even when it's the uncompressed one.
+        response.headers.append_unchecked("Vary", "Accept-Encoding");
+
+        response
+    }
+
+    /// Finds the precompressed sibling of the file in the best encoding the client accepts.
+    fn encoded(&self, request: &Request, path: &Path) -> Option<(PathBuf, &'static str)> {
+        let accept = request.headers.get("Accept-Encoding")?;
+        let mut supported = Vec::new();
+
+        for (encoding, extension) in [("br", "br"), ("gzip", "gz")] {
+            let mut sibling = path.as_os_str().to_owned();
+
+            sibling.push(".");
+            sibling.push(extension);
+
+            // The sibling could be a symlink pointing outside of the directory too.
+            let Ok(sibling) = PathBuf::from(sibling).canonicalize() else {
+                continue;
+            };
+
+            if sibling.starts_with(self.root.canonicalize().ok()?) && sibling.is_file() {
+                supported.push((encoding, sibling));
+            }
+        }
+
+        let encodings: Vec<&'static str> = supported.iter().map(|(e, _)| *e).collect();
+        let encoding = negotiate(accept, &encodings)?;
+
+        supported
+            .into_iter()
+            .find(|(e, _)| *e == encoding)
+            .map(|(encoding, sibling)| (sibling, encoding))
+    }
+
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let mut file_path = self.root.clone();
+
+        for segment in path.split('/') {
+            if segment.is_empty() || segment == "." {
+                continue;
+            }
+
+            let mut components = Path::new(segment).components();
+
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(_)), None) if !segment.contains(['\\', '\0']) => {
+                    file_path.push(segment)
+                }
+                _ => return None,
+            }
+        }
+
+        if file_path.is_dir() {
+            file_path.push("index.html");
+        }
+
+        let file_path = file_path.canonicalize().ok()?;
+
+        if !file_path.starts_with(self.root.canonicalize().ok()?) || !file_path.is_file() {
+            return None;
+        }
+
+        Some(file_path)
+    }
+
+    /// Sends the file at `path`, or its precompressed sibling if `encoded` names one.
+    fn respond(
+        &self,
+        request: &Request,
+        path: &Path,
+        encoded: Option<(PathBuf, &'static str)>,
+    ) -> Response {
+        let Ok(mut file) = File::open(encoded.as_ref().map_or(path, |(sibling, _)| sibling)) else {
+            return Response::not_found();
+        };
+        let Ok(metadata) = file.metadata() else {
+            return Response::not_found();
+        };
+        let length = metadata.len();
+        let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+        let mtime = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let etag = format!("\"{:x}-{:x}\"", mtime.as_secs(), length);
+        let last_modified = date::format(modified);
+
+        let mut response = if self.is_fresh(request, &etag, modified) {
+            Response::new(StatusCode::NOT_MODIFIED, "")
+        } else {
+            let range = match request.headers.get("range") {
+                Some(range) if self.if_range(request, &etag, &last_modified) => {
+                    parse_range(range, length)
+                }
+                _ => None,
+            };
+
+            match range {
+                None => {
+                    let mut response = Response::new(StatusCode::OK, Body::File(file, length));
+
+                    response
+                        .headers
+                        .insert_unchecked("Content-Type", mime_type(path));
+
+                    response
+                }
+                Some(Some((start, end))) => {
+                    if file.seek(SeekFrom::Start(start)).is_err() {
+                        return Response::not_found();
+                    }
+
+                    let mut response = Response::new(
+                        StatusCode::PARTIAL_CONTENT,
+                        Body::File(file, end - start + 1),
+                    );
+
+                    response
+                        .headers
+                        .insert_unchecked("Content-Type", mime_type(path));
+                    response.headers.insert_unchecked(
+                        "Content-Range",
+                        &format!("bytes {start}-{end}/{length}"),
+                    );
+
+                    response
+                }
+                Some(None) => {
+                    let mut response =
+                        Response::new(StatusCode::RANGE_NOT_SATISFIABLE, "Range Not Satisfiable");
+
+                    response
+                        .headers
+                        .insert_unchecked("Content-Range", &format!("bytes */{length}"));
+
+                    response
+                }
+            }
+        };
+
+        let cache_control = match self.max_age {
+            Some(max_age) => format!("public, max-age={}", max_age.as_secs()),
+            None => "public, no-cache".to_string(),
+        };
+
+        response
+            .headers
+            .insert_unchecked("Cache-Control", &cache_control);
+        response.headers.insert_unchecked("ETag", &etag);
+        response
+            .headers
+            .insert_unchecked("Last-Modified", &last_modified);
+        response.headers.insert_unchecked("Accept-Ranges", "bytes");
+
+        if let Some((_, encoding)) = encoded {
+            response
+                .headers
+                .insert_unchecked("Content-Encoding", encoding);
+        }
+
+        response
+    }
+
+    /// Tells whether the client's cached copy is still current. `If-None-Match` takes precedence
+    /// over `If-Modified-Since`, as RFC 9110 requires.
+    fn is_fresh(&self, request: &Request, etag: &str, modified: SystemTime) -> bool {
+        if let Some(tags) = request.headers.get("if-none-match") {
+            return tags.trim() == "*"
+                || tags
+                    .split(',')
+                    .any(|tag| tag.trim().trim_start_matches("W/") == etag);
+        }
+
+        match request
+            .headers
+            .get("if-modified-since")
+            .and_then(date::parse)
+        {
+            // HTTP dates have whole seconds, so the fraction of the modification time is dropped.
+            Some(since) => {
+                let modified = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+                UNIX_EPOCH + Duration::from_secs(modified.as_secs()) <= since
+            }
+            None => false,
+        }
+    }
+
+    /// Tells whether a `Range` should be honoured: only if the client's partial copy, named by
+    /// `If-Range`, is still the current one.
+    fn if_range(&self, request: &Request, etag: &str, last_modified: &str) -> bool {
+        match request.headers.get("if-range") {
+            Some(value) => value == etag || value == last_modified,
+            None => true,
+        }
+    }
+}
+
+/// Parses a `Range` header into the inclusive range of bytes to send. Returns `None` when the
+/// header should be ignored, such as for several ranges, and `Some(None)` when the range lies
+/// outside of the file.
+fn parse_range(value: &str, length: u64) -> Option<Option<(u64, u64)>> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+
+    if spec.contains(',') {
+        return None;
+    }
+
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        let suffix: u64 = end.parse().ok()?;
+
+        if suffix == 0 || length == 0 {
+            return Some(None);
+        }
+
+        return Some(Some((length.saturating_sub(suffix), length - 1)));
+    }
+
+    let start: u64 = start.parse().ok()?;
+    let end = match end {
+        "" => u64::MAX,
+        end => end.parse().ok()?,
+    };
+
+    if end < start {
+        return None;
+    }
+
+    if start >= length {
+        return Some(None);
+    }
+
+    Some(Some((start, end.min(length - 1))))
+}
+
+/// Guesses the `Content-Type` of a file from its extension.
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=UTF-8",
+        "css" => "text/css; charset=UTF-8",
+        "js" | "mjs" => "text/javascript; charset=UTF-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=UTF-8",
+        "csv" => "text/csv; charset=UTF-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "wasm" => "application/wasm",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{Request, StaticFiles};
+    use tempfile::TempDir;
+
+    fn public_dir() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+
+        std::fs::write(dir.path().join("app.css"), "body { color: red }").unwrap();
+
+        dir
+    }
+
+    fn get(files: &StaticFiles, uri: &str, headers: &[(&str, &str)]) -> crate::http::Response {
+        let mut request = Request::new("GET".to_string(), uri.to_string());
+
+        for (name, value) in headers {
+            request.headers.insert(name, value).unwrap();
+        }
+
+        files.handle(&request)
+    }
+
+    #[test]
+    fn it_serves_files_and_blocks_traversal() {
+        let dir = public_dir();
+        let files = StaticFiles::new("/assets", dir.path());
+
+        let response = get(&files, "/assets/app.css", &[]);
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.headers.get("Content-Type").unwrap(),
+            "text/css; charset=UTF-8"
+        );
+
+        assert_eq!(get(&files, "/assets/../Cargo.toml", &[]).status, 404);
+        assert_eq!(get(&files, "/assets/%2e%2e/Cargo.toml", &[]).status, 404);
+        assert_eq!(get(&files, "/assetsapp.css", &[]).status, 404);
+    }
+
+    #[test]
+    fn it_revalidates_with_etags() {
+        let dir = public_dir();
+        let files = StaticFiles::new("/assets", dir.path());
+
+        let etag = get(&files, "/assets/app.css", &[])
+            .headers
+            .get("ETag")
+            .unwrap()
+            .to_string();
+
+        let response = get(&files, "/assets/app.css", &[("if-none-match", &etag)]);
+        assert_eq!(response.status, 304);
+        assert!(response.body.is_empty());
+    }
+
+    #[test]
+    fn it_serves_precompressed_siblings() {
+        let dir = public_dir();
+        std::fs::write(dir.path().join("app.css.gz"), "gzipped").unwrap();
+        let files = StaticFiles::new("/assets", dir.path()).precompressed(true);
+
+        let mut response = get(
+            &files,
+            "/assets/app.css",
+            &[("accept-encoding", "gzip, br")],
+        );
+        assert_eq!(response.headers.get("Content-Encoding"), Some("gzip"));
+        assert_eq!(
+            response.headers.get("Content-Type"),
+            Some("text/css; charset=UTF-8")
+        );
+        assert_eq!(response.headers.get("Vary"), Some("Accept-Encoding"));
+        let mut output = Vec::new();
+        response.send(&mut output).unwrap();
+        assert!(output.ends_with(b"\r\n\r\ngzipped"));
+
+        let response = get(&files, "/assets/app.css", &[]);
+        assert_eq!(response.headers.get("Content-Encoding"), None);
+    }
+
+    #[test]
+    fn it_serves_byte_ranges() {
+        let dir = public_dir();
+        let files = StaticFiles::new("/assets", dir.path());
+        let mut output = Vec::new();
+
+        let mut response = get(&files, "/assets/app.css", &[("range", "bytes=0-3")]);
+        response.send(&mut output).unwrap();
+
+        assert_eq!(response.status, 206);
+        assert_eq!(
+            response.headers.get("Content-Range").unwrap(),
+            "bytes 0-3/19"
+        );
+        assert!(output.ends_with(b"Content-Length: 4\r\n\r\nbody"));
+
+        let response = get(&files, "/assets/app.css", &[("range", "bytes=100-")]);
+        assert_eq!(response.status, 416);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::http::Error;
+
+/// Header fields in the order they were received or set. Names are matched case-insensitively,
+/// and a name may have several values, each kept as its own field, as `Set-Cookie` requires.
+#[derive(Debug, Default, Clone)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> HeaderMap {
+        HeaderMap::default()
+    }
+
+    /// Sets the header, replacing all of its previous values.
+    ///
+    /// Fails with `Error::InvalidHeader` if the name isn't a token or the value contains control
+    /// characters, so that a line break smuggled into a value can't inject headers of its own.
+    pub fn insert(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        validate(name, value)?;
+        self.insert_unchecked(name, value);
+
+        Ok(())
+    }
+
+    /// Adds a value to the header, keeping the previous ones. Validates like `insert`.
+    pub fn append(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        validate(name, value)?;
+        self.append_unchecked(name, value);
+
+        Ok(())
+    }
+
+    /// Sets a header the library knows to be valid.
+    pub(crate) fn insert_unchecked(&mut self, name: &str, value: &str) {
+        self.remove(name);
+        self.append_unchecked(name, value);
+    }
+
+    pub(crate) fn append_unchecked(&mut self, name: &str, value: &str) {
+        self.entries.push((name.to_string(), value.to_string()));
+    }
+
+    /// Returns the first value of the header.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.entries.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+pub(crate) fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|c| c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c))
+}
+
+fn validate(name: &str, value: &str) -> Result<(), Error> {
+    let valid_value = value
+        .bytes()
+        .all(|c| c == b'\t' || (c >= b' ' && c != 0x7f));
+
+    if !is_token(name) || !valid_value {
+        return Err(Error::InvalidHeader);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{Error, HeaderMap};
+
+    #[test]
+    fn it_looks_up_headers_case_insensitively() {
+        let mut headers = HeaderMap::new();
+
+        headers.insert("Vary", "Accept").unwrap();
+        headers.append("vary", "Accept-Encoding").unwrap();
+
+        assert_eq!(headers.get("VARY"), Some("Accept"));
+        assert_eq!(headers.get_all("Vary"), vec!["Accept", "Accept-Encoding"]);
+
+        headers.insert("VARY", "Cookie").unwrap();
+
+        assert_eq!(headers.get_all("vary"), vec!["Cookie"]);
+    }
+
+    #[test]
+    fn it_rejects_header_injection() {
+        let mut headers = HeaderMap::new();
+
+        assert!(matches!(
+            headers.insert("Location", "/\r\nSet-Cookie: admin=1"),
+            Err(Error::InvalidHeader)
+        ));
+        assert!(matches!(
+            headers.insert("Bad Name", "value"),
+            Err(Error::InvalidHeader)
+        ));
+        assert!(headers.is_empty());
+    }
+}
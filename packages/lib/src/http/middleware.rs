@@ -0,0 +1,232 @@
+use crate::http::{ErrorStatus, Request, Response};
+use std::fmt::Debug;
+
+/// Wraps request handling, for concerns that apply to many routes such as logging,
+/// authentication or error rendering.
+///
+/// A middleware receives the request together with `next`, the rest of the pipeline. It may
+/// inspect or change the request before calling `next.run(request)`, change the response after,
+/// or answer on its own without calling `next` at all. Middleware runs in the order it's added,
+/// so the first one added sees the request first and the response last.
+pub trait Middleware<E>: Send + Sync {
+    fn handle(&self, request: &mut Request, next: Next<E>) -> Result<Response, E>;
+
+    /// A short name for listings such as `route:list`. Defaults to the name of the type, or of
+    /// the function a closure is defined in.
+    fn name(&self) -> String {
+        let name = std::any::type_name::<Self>();
+        // Generic arguments, as in `Sessions<FileStore>`, are left out.
+        let name = name.split('<').next().unwrap_or(name);
+
+        name.rsplit("::")
+            .find(|segment| *segment != "{{closure}}")
+            .unwrap_or(name)
+            .to_string()
+    }
+}
+
+impl<E, F> Middleware<E> for F
+where
+    F: Fn(&mut Request, Next<E>) -> Result<Response, E> + Send + Sync,
+{
+    fn handle(&self, request: &mut Request, next: Next<E>) -> Result<Response, E> {
+        self(request, next)
+    }
+}
+
+/// The remaining middleware and the handler at the end of the pipeline.
+pub struct Next<'a, E> {
+    middleware: &'a [std::sync::Arc<dyn Middleware<E>>],
+    handler: &'a dyn Fn(&mut Request) -> Result<Response, E>,
+}
+
+impl<'a, E> Next<'a, E> {
+    pub(crate) fn new(
+        middleware: &'a [std::sync::Arc<dyn Middleware<E>>],
+        handler: &'a dyn Fn(&mut Request) -> Result<Response, E>,
+    ) -> Next<'a, E> {
+        Next {
+            middleware,
+            handler,
+        }
+    }
+
+    pub fn run(self, request: &mut Request) -> Result<Response, E> {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.handle(request, Next::new(rest, self.handler)),
+            None => (self.handler)(request),
+        }
+    }
+}
+
+/// Runs `hook` before the rest of the pipeline. If it returns a response, the pipeline stops
+/// there and that response is sent instead.
+pub fn before<E, F>(hook: F) -> impl Middleware<E>
+where
+    F: Fn(&mut Request) -> Result<Option<Response>, E> + Send + Sync,
+{
+    move |request: &mut Request, next: Next<E>| match hook(request)? {
+        Some(response) => Ok(response),
+        None => next.run(request),
+    }
+}
+
+/// Runs `hook` on the response once the rest of the pipeline has produced it.
+pub fn after<E, F>(hook: F) -> impl Middleware<E>
+where
+    F: Fn(&Request, &mut Response) -> Result<(), E> + Send + Sync,
+{
+    move |request: &mut Request, next: Next<E>| {
+        let mut response = next.run(request)?;
+
+        hook(request, &mut response)?;
+
+        Ok(response)
+    }
+}
+
+/// Prints each request with the status of its response, marking XHR requests.
+pub struct Log;
+
+impl<E> Middleware<E> for Log {
+    fn handle(&self, request: &mut Request, next: Next<E>) -> Result<Response, E> {
+        let xhr = if request.headers.contains_key("x-requested-with") {
+            "XHR "
+        } else {
+            ""
+        };
+        let line = format!("{xhr}{} {}", request.method, request.uri);
+
+        let result = next.run(request);
+
+        match &result {
+            Ok(response) => println!("{line} -> {}", response.status),
+            Err(_) => println!("{line} -> error"),
+        }
+
+        result
+    }
+}
+
+/// Turns handler errors into responses with the status the error reports. Errors can tell of
+/// paths or queries, so the response only carries the reason phrase, and the error is printed.
+pub struct RenderErrors;
+
+impl<E: Debug + ErrorStatus> Middleware<E> for RenderErrors {
+    fn handle(&self, request: &mut Request, next: Next<E>) -> Result<Response, E> {
+        match next.run(request) {
+            Ok(response) => Ok(response),
+            Err(err) => {
+                let status = err.status();
+
+                eprintln!("{} {} failed: {err:?}", request.method, request.uri);
+
+                Ok(Response::new(status, status.reason()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::middleware::{after, before, Next, RenderErrors};
+    use crate::http::{Error, Request, Response, Router, StatusCode};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn it_runs_middleware_in_order_around_the_handler() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut router: Router<()> = Router::new();
+
+        for name in ["outer", "inner"] {
+            let calls = calls.clone();
+
+            router.middleware(move |request: &mut Request, next: Next<()>| {
+                calls.lock().unwrap().push(format!("{name} before"));
+                let response = next.run(request);
+                calls.lock().unwrap().push(format!("{name} after"));
+
+                response
+            });
+        }
+
+        let handler_calls = calls.clone();
+        router.get("/", move |_| {
+            handler_calls.lock().unwrap().push("handler".to_string());
+
+            Ok(Response::plain_text("home".to_string()))
+        });
+
+        router
+            .handle(&mut Request::new("GET".to_string(), "/".to_string()))
+            .unwrap();
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "outer before",
+                "inner before",
+                "handler",
+                "inner after",
+                "outer after"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_renders_errors_without_their_details() {
+        let mut router: Router<Error> = Router::new();
+
+        router.middleware(RenderErrors);
+        router.get("/posts/{id}", |request| {
+            request.param::<i64>("id")?;
+
+            Err(Error::Io(std::io::Error::other("/var/app/storage is full")))
+        });
+
+        let mut request = Request::new("GET".to_string(), "/posts/1".to_string());
+        let response = router.handle(&mut request).unwrap();
+        assert_eq!(response.status, 500);
+        assert_eq!(response.body.as_bytes(), b"Internal Server Error");
+
+        let mut request = Request::new("GET".to_string(), "/posts/one".to_string());
+        let response = router.handle(&mut request).unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.body.as_bytes(), b"Not Found");
+    }
+
+    #[test]
+    fn it_applies_route_middleware_and_hooks() {
+        let mut router: Router<()> = Router::new();
+
+        router
+            .get("/admin", |_| Ok(Response::plain_text("admin".to_string())))
+            .middleware(before(|request: &mut Request| {
+                if request.headers.contains_key("authorization") {
+                    return Ok(None);
+                }
+
+                Ok(Some(Response::new(
+                    StatusCode::UNAUTHORIZED,
+                    "Unauthorized",
+                )))
+            }))
+            .middleware(after(|_: &Request, response: &mut Response| {
+                response.header("X-Admin", "true").unwrap();
+
+                Ok(())
+            }));
+        router.get("/", |_| Ok(Response::plain_text("home".to_string())));
+
+        let mut request = Request::new("GET".to_string(), "/admin".to_string());
+        assert_eq!(router.handle(&mut request).unwrap().status, 401);
+
+        request.headers.insert("Authorization", "secret").unwrap();
+        let response = router.handle(&mut request).unwrap();
+        assert_eq!(response.body.as_bytes(), b"admin");
+        assert!(response.headers.contains_key("X-Admin"));
+
+        let mut request = Request::new("GET".to_string(), "/".to_string());
+        assert_eq!(router.handle(&mut request).unwrap().status, 200);
+    }
+}
@@ -0,0 +1,434 @@
+use crate::http::Error;
+use std::io::{Read, Write};
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+/// Reads a `multipart/form-data` body one part at a time. Text fields are returned as strings,
+/// and files are copied to temporary files.
+///
+/// Uploads aren't streamed: the server buffers the whole request body before the handler runs,
+/// and `Limits::max_body_size` is what bounds it, checked as the body is read from the
+/// connection. The limits here apply on top of that, to the parts themselves, and have to stay
+/// below it to have any effect.
+///
+/// Exceeding `max_file_size` or `max_total_size` fails with `Error::BodyTooLarge`. Bytes before
+/// the first boundary don't count.
+pub struct Multipart<R> {
+    reader: R,
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    max_file_size: u64,
+    max_total_size: u64,
+    total_size: u64,
+    started: bool,
+    done: bool,
+}
+
+pub enum Part {
+    Text { name: String, value: String },
+    File(UploadedFile),
+}
+
+/// A file part of a multipart body. The temporary file is deleted when this is dropped, unless
+/// it's moved away with `persist`.
+pub struct UploadedFile {
+    /// The name of the form field.
+    pub name: String,
+    /// The name of the file on the client, without any directories.
+    pub filename: String,
+    pub content_type: String,
+    pub size: u64,
+    file: NamedTempFile,
+}
+
+impl UploadedFile {
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Moves the file to its permanent place.
+    pub fn persist<P: AsRef<Path>>(self, path: P) -> Result<(), Error> {
+        self.file
+            .persist(path)
+            .map(|_| ())
+            .map_err(|e| Error::Io(e.error))
+    }
+}
+
+/// Headers of parts are short, so anything longer is malformed.
+const MAX_PART_HEADERS: usize = 8 * 1024;
+
+impl<R: Read> Multipart<R> {
+    pub fn new(reader: R, boundary: &str) -> Multipart<R> {
+        Multipart {
+            reader,
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            // The first delimiter isn't preceded by a line break, so one is made up for it.
+            buffer: b"\r\n".to_vec(),
+            max_file_size: 2 * 1024 * 1024,
+            max_total_size: 8 * 1024 * 1024,
+            total_size: 0,
+            started: false,
+            done: false,
+        }
+    }
+
+    pub fn max_file_size(mut self, max_file_size: u64) -> Multipart<R> {
+        self.max_file_size = max_file_size;
+
+        self
+    }
+
+    pub fn max_total_size(mut self, max_total_size: u64) -> Multipart<R> {
+        self.max_total_size = max_total_size;
+
+        self
+    }
+
+    /// Returns the next part, or `None` after the last one. File inputs left empty, which
+    /// browsers send with an empty file name, are skipped.
+    pub fn next_part(&mut self) -> Result<Option<Part>, Error> {
+        loop {
+            let Some(headers) = self.next_headers()? else {
+                return Ok(None);
+            };
+            let disposition = header(&headers, "content-disposition").unwrap_or("");
+            let name = parameter(disposition, "name").ok_or(Error::InvalidMultipart)?;
+
+            let Some(filename) = parameter(disposition, "filename") else {
+                let mut value = Vec::new();
+
+                self.read_part(&mut value, u64::MAX)?;
+
+                return Ok(Some(Part::Text {
+                    name,
+                    value: String::from_utf8_lossy(&value).to_string(),
+                }));
+            };
+
+            let mut file = NamedTempFile::new().map_err(Error::Io)?;
+            let size = self.read_part(&mut file, self.max_file_size)?;
+
+            if filename.is_empty() {
+                continue;
+            }
+
+            return Ok(Some(Part::File(UploadedFile {
+                name,
+                filename: filename
+                    .rsplit(['/', '\\'])
+                    .next()
+                    .unwrap_or("")
+                    .to_string(),
+                content_type: header(&headers, "content-type")
+                    .unwrap_or("application/octet-stream")
+                    .to_string(),
+                size,
+                file,
+            })));
+        }
+    }
+
+    /// Returns the value of the first text field called `name`. The bodies of files are skipped
+    /// without being stored, and don't count against the size limits.
+    pub(crate) fn text_field(mut self, name: &str) -> Result<Option<String>, Error> {
+        while let Some(headers) = self.next_headers()? {
+            let disposition = header(&headers, "content-disposition").unwrap_or("");
+
+            if parameter(disposition, "filename").is_some() {
+                self.read_body(&mut std::io::sink(), u64::MAX)?;
+            } else if parameter(disposition, "name").as_deref() == Some(name) {
+                let mut value = Vec::new();
+
+                self.read_part(&mut value, u64::MAX)?;
+
+                return Ok(Some(String::from_utf8_lossy(&value).to_string()));
+            } else {
+                self.read_part(&mut std::io::sink(), u64::MAX)?;
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Moves on to the next part and reads its headers, or returns `None` after the last one.
+    fn next_headers(&mut self) -> Result<Option<Vec<(String, String)>>, Error> {
+        if !self.started {
+            // Skips the preamble and the first delimiter.
+            self.started = true;
+            self.read_body(&mut std::io::sink(), u64::MAX)?;
+        }
+
+        if self.done || !self.end_delimiter()? {
+            self.done = true;
+            return Ok(None);
+        }
+
+        self.read_headers().map(Some)
+    }
+
+    /// Reads more of the body into the buffer, returning `false` at the end of it.
+    fn fill(&mut self) -> Result<bool, Error> {
+        let mut chunk = [0u8; 8192];
+        let read = self.reader.read(&mut chunk).map_err(Error::Io)?;
+
+        self.buffer.extend_from_slice(&chunk[..read]);
+
+        Ok(read > 0)
+    }
+
+    /// Consumes the rest of the line after a delimiter. Returns `false` if it was the closing
+    /// delimiter.
+    fn end_delimiter(&mut self) -> Result<bool, Error> {
+        while self.buffer.len() < 2 {
+            if !self.fill()? {
+                return Err(Error::InvalidMultipart);
+            }
+        }
+
+        if self.buffer.starts_with(b"--") {
+            return Ok(false);
+        }
+
+        loop {
+            if let Some(end) = find(&self.buffer, b"\r\n") {
+                // Only transport padding may come between the delimiter and the line break.
+                if !self.buffer[..end].iter().all(|&c| c == b' ' || c == b'\t') {
+                    return Err(Error::InvalidMultipart);
+                }
+
+                self.buffer.drain(..end + 2);
+                return Ok(true);
+            }
+
+            if self.buffer.len() > MAX_PART_HEADERS || !self.fill()? {
+                return Err(Error::InvalidMultipart);
+            }
+        }
+    }
+
+    fn read_headers(&mut self) -> Result<Vec<(String, String)>, Error> {
+        let end = loop {
+            if self.buffer.starts_with(b"\r\n") {
+                break 0;
+            }
+
+            if let Some(end) = find(&self.buffer, b"\r\n\r\n") {
+                break end + 2;
+            }
+
+            if self.buffer.len() > MAX_PART_HEADERS || !self.fill()? {
+                return Err(Error::InvalidMultipart);
+            }
+        };
+
+        let headers = String::from_utf8_lossy(&self.buffer[..end])
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+            .collect();
+
+        self.buffer.drain(..end + 2);
+
+        Ok(headers)
+    }
+
+    /// Reads the part's body like `read_body`, counting it against `max_total_size`.
+    fn read_part<W: Write>(&mut self, sink: &mut W, max_size: u64) -> Result<u64, Error> {
+        let remaining = self.max_total_size.saturating_sub(self.total_size);
+        let size = self.read_body(sink, max_size.min(remaining))?;
+
+        self.total_size += size;
+
+        Ok(size)
+    }
+
+    /// Copies the part's body to `sink` and consumes the delimiter after it. Returns the size
+    /// of the body, or fails with `Error::BodyTooLarge` once it exceeds `max_size`.
+    fn read_body<W: Write>(&mut self, sink: &mut W, max_size: u64) -> Result<u64, Error> {
+        let mut size = 0;
+
+        loop {
+            let (end, found) = match find(&self.buffer, &self.delimiter) {
+                Some(end) => (end, true),
+                // The tail may be the start of a delimiter cut in half, so it's kept for later.
+                None => (
+                    self.buffer.len().saturating_sub(self.delimiter.len() - 1),
+                    false,
+                ),
+            };
+
+            size += end as u64;
+
+            if size > max_size {
+                return Err(Error::BodyTooLarge);
+            }
+
+            sink.write_all(&self.buffer[..end]).map_err(Error::Io)?;
+            self.buffer.drain(..end);
+
+            if found {
+                self.buffer.drain(..self.delimiter.len());
+                return Ok(size);
+            }
+
+            if !self.fill()? {
+                return Err(Error::InvalidMultipart);
+            }
+        }
+    }
+}
+
+/// Returns the `boundary` parameter of a `multipart/form-data` content type.
+pub(crate) fn boundary(content_type: &str) -> Option<String> {
+    let (mime, _) = content_type.split_once(';').unwrap_or((content_type, ""));
+
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+
+    parameter(content_type, "boundary").filter(|boundary| !boundary.is_empty())
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Returns a `name=value` or `name="value"` parameter of a header value.
+fn parameter(header: &str, name: &str) -> Option<String> {
+    header.split(';').skip(1).find_map(|parameter| {
+        let (n, value) = parameter.split_once('=')?;
+
+        if !n.trim().eq_ignore_ascii_case(name) {
+            return None;
+        }
+
+        let value = value.trim();
+
+        Some(
+            match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+                Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+                None => value.to_string(),
+            },
+        )
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::multipart::Part;
+    use crate::http::{Error, Request};
+
+    fn request(body: &str) -> Request {
+        let mut request = Request::new("POST".to_string(), "/upload".to_string());
+
+        request
+            .headers
+            .insert("Content-Type", "multipart/form-data; boundary=XyZ")
+            .unwrap();
+        request.body = body.replace('\n', "\r\n").into_bytes();
+
+        request
+    }
+
+    #[test]
+    fn it_parses_fields_and_files() {
+        let request = request(
+            "preamble
+--XyZ
+Content-Disposition: form-data; name=\"title\"
+
+Hello, --XyZ world
+--XyZ
+Content-Disposition: form-data; name=\"avatar\"; filename=\"C:\\\\photos\\\\me.png\"
+Content-Type: image/png
+
+PNG\x00DATA
+--XyZ
+Content-Disposition: form-data; name=\"empty\"; filename=\"\"
+Content-Type: application/octet-stream
+
+
+--XyZ--
+",
+        );
+        let mut multipart = request.multipart().unwrap();
+
+        let Some(Part::Text { name, value }) = multipart.next_part().unwrap() else {
+            panic!("expected a text field");
+        };
+        assert_eq!(
+            (name.as_str(), value.as_str()),
+            ("title", "Hello, --XyZ world")
+        );
+
+        let Some(Part::File(file)) = multipart.next_part().unwrap() else {
+            panic!("expected a file");
+        };
+        assert_eq!(file.name, "avatar");
+        assert_eq!(file.filename, "me.png");
+        assert_eq!(file.content_type, "image/png");
+        assert_eq!(file.size, 8);
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"PNG\x00DATA");
+
+        assert!(multipart.next_part().unwrap().is_none());
+    }
+
+    #[test]
+    fn it_limits_file_sizes() {
+        let request = request(
+            "--XyZ
+Content-Disposition: form-data; name=\"csv\"; filename=\"import.csv\"
+
+a,b,c
+--XyZ--
+",
+        );
+        let mut multipart = request.multipart().unwrap().max_file_size(4);
+
+        assert!(matches!(multipart.next_part(), Err(Error::BodyTooLarge)));
+    }
+
+    #[test]
+    fn it_leaves_the_preamble_out_of_the_limits() {
+        let request = request(
+            "a long preamble that no client shows
+--XyZ
+Content-Disposition: form-data; name=\"title\"
+
+Hello
+--XyZ--
+",
+        );
+        let mut multipart = request.multipart().unwrap().max_total_size(5);
+
+        assert!(matches!(
+            multipart.next_part(),
+            Ok(Some(Part::Text { value, .. })) if value == "Hello"
+        ));
+        assert!(matches!(multipart.next_part(), Ok(None)));
+    }
+
+    #[test]
+    fn it_decodes_urlencoded_forms() {
+        let mut request = Request::new("POST".to_string(), "/".to_string());
+        request
+            .headers
+            .insert("Content-Type", "application/x-www-form-urlencoded")
+            .unwrap();
+        request.body = b"tag=a&tag=b+c&name=%C3%A9".to_vec();
+
+        let form = request.form();
+        assert_eq!(form.get_all("tag"), vec!["a", "b c"]);
+        assert_eq!(form.get("name"), Some("é"));
+    }
+}
@@ -0,0 +1,855 @@
+use crate::http::{percent_decode, Middleware, Next, Request, Response, StaticFiles, Urls};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+/// Produces the response for a matched route.
+pub type Handler<E> = Box<dyn Fn(&mut Request) -> Result<Response, E> + Send + Sync>;
+
+/// Dispatches requests to handlers registered with `"METHOD /pattern"` routes, the same syntax
+/// `Request::is` accepts.
+///
+/// Routes are compiled into a tree of path segments as they are added, so a request is matched
+/// in a single walk down the tree. Literal segments take precedence over `{parameter}` segments,
+/// which take precedence over a trailing `{wildcard*}`.
+///
+/// A parameter may be constrained, as in `{id:int}` or `{slug:[a-z-]+}`. A segment that doesn't
+/// satisfy the constraint doesn't match, so the request falls through to the next route.
+/// Constrained parameters are tried before unconstrained ones.
+pub struct Router<E> {
+    routes: Vec<Route<E>>,
+    root: Node,
+    middleware: Vec<Arc<dyn Middleware<E>>>,
+    urls: OnceLock<Arc<Urls>>,
+    groups: Vec<GroupOptions<E>>,
+}
+
+/// Options shared by the routes of a group, built with `Router::group`.
+pub struct Group<'a, E> {
+    router: &'a mut Router<E>,
+    options: GroupOptions<E>,
+}
+
+struct GroupOptions<E> {
+    prefix: String,
+    name: String,
+    middleware: Vec<Arc<dyn Middleware<E>>>,
+}
+
+pub struct Route<E> {
+    pub method: String,
+    pub pattern: String,
+    pub name: Option<String>,
+    parameters: Vec<String>,
+    handler: Handler<E>,
+    middleware: Vec<Arc<dyn Middleware<E>>>,
+    name_prefix: String,
+}
+
+#[derive(Default)]
+struct Node {
+    literals: HashMap<String, Node>,
+    parameters: Vec<(Option<Constraint>, Node)>,
+    wildcard: Vec<usize>,
+    routes: Vec<usize>,
+}
+
+enum Segment<'a> {
+    Literal(&'a str),
+    Parameter(&'a str, Option<&'a str>),
+    Wildcard(&'a str),
+}
+
+/// What a constrained parameter accepts: `int`, `alpha`, `alnum`, or a sequence of characters
+/// and `[...]` classes, each optionally followed by `?`, `*`, `+`, `{n}` or `{n,m}`. The whole
+/// segment must match.
+struct Constraint {
+    source: String,
+    atoms: Vec<Atom>,
+}
+
+struct Atom {
+    class: Class,
+    min: usize,
+    max: usize,
+}
+
+enum Class {
+    Any,
+    Char(char),
+    Set {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+enum Match {
+    Found(usize, Vec<String>),
+    MethodNotAllowed(Vec<String>),
+    NotFound,
+}
+
+impl<E> Router<E> {
+    pub fn new() -> Router<E> {
+        Router {
+            routes: Vec::new(),
+            root: Node::default(),
+            middleware: Vec::new(),
+            urls: OnceLock::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    /// Adds middleware around every request the router handles, including those answered with
+    /// 404 or 405.
+    pub fn middleware<M>(&mut self, middleware: M) -> &mut Router<E>
+    where
+        M: Middleware<E> + 'static,
+    {
+        self.middleware.push(Arc::new(middleware));
+
+        self
+    }
+
+    /// Starts a group of routes sharing a path prefix, and optionally a name prefix and
+    /// middleware, as in `router.group("/admin").name("admin.").middleware(Auth).routes(|router|
+    /// { ... })`. Groups nest, each adding to the options of the groups around it.
+    pub fn group(&mut self, prefix: &str) -> Group<'_, E> {
+        Group {
+            router: self,
+            options: GroupOptions {
+                prefix: prefix.trim_end_matches('/').to_string(),
+                name: String::new(),
+                middleware: Vec::new(),
+            },
+        }
+    }
+
+    /// Registers a handler for a route such as `"GET /posts/{id}"`.
+    ///
+    /// Panics if the route is malformed, as routes are part of the program rather than input.
+    pub fn add<F>(&mut self, route: &str, handler: F) -> &mut Route<E>
+    where
+        F: Fn(&mut Request) -> Result<Response, E> + Send + Sync + 'static,
+    {
+        let (method, pattern) = route
+            .split_once(' ')
+            .unwrap_or_else(|| panic!("Route `{route}` must be `METHOD /pattern`"));
+        let prefix: String = self.groups.iter().map(|g| g.prefix.as_str()).collect();
+        let pattern = match pattern {
+            "/" if !prefix.is_empty() => prefix,
+            _ => format!("{prefix}{pattern}"),
+        };
+
+        self.insert(method, &pattern, Box::new(handler))
+    }
+
+    fn insert(&mut self, method: &str, pattern: &str, handler: Handler<E>) -> &mut Route<E> {
+        let segments = parse_pattern(pattern);
+        let index = self.routes.len();
+        let mut node = &mut self.root;
+        let mut parameters = Vec::new();
+
+        for (i, segment) in segments.iter().enumerate() {
+            node = match segment {
+                Segment::Literal(literal) => node.literals.entry(literal.to_string()).or_default(),
+                Segment::Parameter(name, constraint) => {
+                    parameters.push(name.to_string());
+                    node.parameter(*constraint)
+                }
+                Segment::Wildcard(name) => {
+                    if i + 1 != segments.len() {
+                        panic!("Wildcard parameter must end route `{method} {pattern}`");
+                    }
+
+                    parameters.push(name.to_string());
+                    node.wildcard.push(index);
+
+                    break;
+                }
+            };
+
+            if i + 1 == segments.len() {
+                node.routes.push(index);
+            }
+        }
+
+        // Built again on first use, once the route has its name.
+        self.urls = OnceLock::new();
+        self.routes.push(Route {
+            method: method.to_string(),
+            pattern: pattern.to_string(),
+            name: None,
+            parameters,
+            handler,
+            middleware: self
+                .groups
+                .iter()
+                .flat_map(|g| g.middleware.iter().cloned())
+                .collect(),
+            name_prefix: self.groups.iter().map(|g| g.name.as_str()).collect(),
+        });
+
+        &mut self.routes[index]
+    }
+
+    pub fn get<F>(&mut self, pattern: &str, handler: F) -> &mut Route<E>
+    where
+        F: Fn(&mut Request) -> Result<Response, E> + Send + Sync + 'static,
+    {
+        self.add(&format!("GET {pattern}"), handler)
+    }
+
+    pub fn post<F>(&mut self, pattern: &str, handler: F) -> &mut Route<E>
+    where
+        F: Fn(&mut Request) -> Result<Response, E> + Send + Sync + 'static,
+    {
+        self.add(&format!("POST {pattern}"), handler)
+    }
+
+    pub fn put<F>(&mut self, pattern: &str, handler: F) -> &mut Route<E>
+    where
+        F: Fn(&mut Request) -> Result<Response, E> + Send + Sync + 'static,
+    {
+        self.add(&format!("PUT {pattern}"), handler)
+    }
+
+    pub fn patch<F>(&mut self, pattern: &str, handler: F) -> &mut Route<E>
+    where
+        F: Fn(&mut Request) -> Result<Response, E> + Send + Sync + 'static,
+    {
+        self.add(&format!("PATCH {pattern}"), handler)
+    }
+
+    pub fn delete<F>(&mut self, pattern: &str, handler: F) -> &mut Route<E>
+    where
+        F: Fn(&mut Request) -> Result<Response, E> + Send + Sync + 'static,
+    {
+        self.add(&format!("DELETE {pattern}"), handler)
+    }
+
+    /// Serves the files of the `root` directory under `prefix`.
+    pub fn files<P: Into<PathBuf>>(&mut self, prefix: &str, root: P) -> &mut Route<E> {
+        self.static_files(StaticFiles::new(prefix, root))
+    }
+
+    /// Serves files configured beyond what `files` allows, such as with `max_age` or
+    /// `precompressed`.
+    pub fn static_files(&mut self, files: StaticFiles) -> &mut Route<E> {
+        let prefix: String = self
+            .groups
+            .iter()
+            .map(|g| g.prefix.as_str())
+            .chain([files.prefix()])
+            .collect();
+        let files = files.with_prefix(&prefix);
+
+        self.insert(
+            "GET",
+            &format!("{}/{{path*}}", files.prefix()),
+            Box::new(move |request| Ok(files.handle(request))),
+        )
+    }
+
+    pub fn routes(&self) -> &[Route<E>] {
+        &self.routes
+    }
+
+    /// Returns the names of the middleware wrapping every route, outermost first.
+    pub fn middleware_names(&self) -> Vec<String> {
+        self.middleware.iter().map(|m| m.name()).collect()
+    }
+
+    /// Returns the named routes, for building URLs.
+    pub fn urls(&self) -> Arc<Urls> {
+        self.urls
+            .get_or_init(|| {
+                let mut urls = Urls::default();
+
+                for route in &self.routes {
+                    if let Some(name) = &route.name {
+                        urls.add(name, &route.method, &route.pattern);
+                    }
+                }
+
+                Arc::new(urls)
+            })
+            .clone()
+    }
+
+    /// Runs the handler of the route matching the request, fills in `request.parameters`, and
+    /// answers 404 if no route matches the path, or 405 if it only matches other methods.
+    ///
+    /// `HEAD` requests without a route of their own are handled by the `GET` route, and `OPTIONS`
+    /// requests without one are answered with the allowed methods.
+    ///
+    /// Router middleware wraps the whole dispatch, and route middleware wraps the handler.
+    pub fn handle(&self, request: &mut Request) -> Result<Response, E> {
+        request.urls = self.urls();
+
+        let mut response =
+            Next::new(&self.middleware, &|request| self.dispatch(request)).run(request)?;
+
+        // Only dropped once all middleware ran, since some, like `Compression`, describe the body
+        // in the headers.
+        if request.method == "HEAD" {
+            response.omit_body();
+        }
+
+        Ok(response)
+    }
+
+    fn dispatch(&self, request: &mut Request) -> Result<Response, E> {
+        match self.find(&request.method, request.path()) {
+            Match::Found(index, values) => {
+                let route = &self.routes[index];
+
+                request.parameters = route.parameters.iter().cloned().zip(values).collect();
+
+                Next::new(&route.middleware, &route.handler).run(request)
+            }
+            Match::MethodNotAllowed(methods) if request.method == "OPTIONS" => {
+                let mut response = Response::no_content();
+
+                response
+                    .headers
+                    .insert_unchecked("Allow", &methods.join(", "));
+
+                Ok(response)
+            }
+            Match::MethodNotAllowed(methods) => Ok(Response::method_not_allowed(&methods)),
+            Match::NotFound => Ok(Response::not_found()),
+        }
+    }
+
+    fn find(&self, method: &str, path: &str) -> Match {
+        let Some(path) = path.strip_prefix('/') else {
+            return Match::NotFound;
+        };
+        let segments: Vec<&str> = path.split('/').collect();
+        let mut values = Vec::new();
+        let mut allowed = Vec::new();
+
+        if let Some(index) = self.find_in(&self.root, &segments, method, &mut values, &mut allowed)
+        {
+            return Match::Found(index, values);
+        }
+
+        if allowed.is_empty() {
+            return Match::NotFound;
+        }
+
+        // `HEAD` falls back to `GET` routes, and `OPTIONS` is answered for any path that exists.
+        if allowed.iter().any(|m| m == "GET") {
+            allowed.push("HEAD".to_string());
+        }
+        allowed.push("OPTIONS".to_string());
+        allowed.sort();
+        allowed.dedup();
+
+        Match::MethodNotAllowed(allowed)
+    }
+
+    fn find_in(
+        &self,
+        node: &Node,
+        segments: &[&str],
+        method: &str,
+        values: &mut Vec<String>,
+        allowed: &mut Vec<String>,
+    ) -> Option<usize> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return self.find_method(&node.routes, method, allowed);
+        };
+
+        if let Some(child) = node.literals.get(*segment) {
+            if let Some(index) = self.find_in(child, rest, method, values, allowed) {
+                return Some(index);
+            }
+        }
+
+        let value = percent_decode(segment);
+
+        for (constraint, child) in &node.parameters {
+            if constraint.as_ref().is_some_and(|c| !c.matches(&value)) {
+                continue;
+            }
+
+            values.push(value.clone());
+
+            if let Some(index) = self.find_in(child, rest, method, values, allowed) {
+                return Some(index);
+            }
+
+            values.pop();
+        }
+
+        if !node.wildcard.is_empty() {
+            if let Some(index) = self.find_method(&node.wildcard, method, allowed) {
+                values.push(percent_decode(&segments.join("/")));
+
+                return Some(index);
+            }
+        }
+
+        None
+    }
+
+    fn find_method(
+        &self,
+        routes: &[usize],
+        method: &str,
+        allowed: &mut Vec<String>,
+    ) -> Option<usize> {
+        for &index in routes {
+            if self.routes[index].method == method {
+                return Some(index);
+            }
+
+            allowed.push(self.routes[index].method.clone());
+        }
+
+        // Without a route of its own, `HEAD` is answered by the `GET` route.
+        if method == "HEAD" {
+            return routes
+                .iter()
+                .copied()
+                .find(|&index| self.routes[index].method == "GET");
+        }
+
+        None
+    }
+}
+
+impl<E> Route<E> {
+    /// Returns the names of the route's middleware, including that of its groups.
+    pub fn middleware_names(&self) -> Vec<String> {
+        self.middleware.iter().map(|m| m.name()).collect()
+    }
+
+    /// Names the route, such as `posts.show`, so URLs to it can be built with `Urls::url_for`.
+    /// The name prefixes of the groups around the route are prepended. Names may only be shared
+    /// by routes with the same pattern, which is checked when `Server::new` builds the URLs.
+    pub fn name(&mut self, name: &str) -> &mut Route<E> {
+        self.name = Some(format!("{}{name}", self.name_prefix));
+
+        self
+    }
+
+    /// Adds middleware that only runs for this route, inside the router's middleware.
+    pub fn middleware<M>(&mut self, middleware: M) -> &mut Route<E>
+    where
+        M: Middleware<E> + 'static,
+    {
+        self.middleware.push(Arc::new(middleware));
+
+        self
+    }
+}
+
+impl<'a, E> Group<'a, E> {
+    /// Prepends `name` to the names of the group's routes, as in `admin.`.
+    pub fn name(mut self, name: &str) -> Group<'a, E> {
+        self.options.name = name.to_string();
+
+        self
+    }
+
+    /// Adds middleware around the handlers of the group's routes, inside the router's middleware
+    /// and outside the routes' own.
+    pub fn middleware<M>(mut self, middleware: M) -> Group<'a, E>
+    where
+        M: Middleware<E> + 'static,
+    {
+        self.options.middleware.push(Arc::new(middleware));
+
+        self
+    }
+
+    /// Registers the group's routes, added to the router passed to `routes`.
+    pub fn routes<F>(self, routes: F)
+    where
+        F: FnOnce(&mut Router<E>),
+    {
+        self.router.groups.push(self.options);
+        routes(self.router);
+        self.router.groups.pop();
+    }
+}
+
+impl Node {
+    /// Returns the child for a parameter with the given constraint, adding it if needed.
+    fn parameter(&mut self, constraint: Option<&str>) -> &mut Node {
+        let position = self
+            .parameters
+            .iter()
+            .position(|(c, _)| c.as_ref().map(|c| c.source.as_str()) == constraint);
+
+        let position = match position {
+            Some(position) => position,
+            None => {
+                let constraint = constraint.map(Constraint::parse);
+                // Unconstrained parameters go last, so constrained ones get a chance first.
+                let position = match constraint {
+                    Some(_) => self
+                        .parameters
+                        .iter()
+                        .take_while(|(c, _)| c.is_some())
+                        .count(),
+                    None => self.parameters.len(),
+                };
+
+                self.parameters
+                    .insert(position, (constraint, Node::default()));
+
+                position
+            }
+        };
+
+        &mut self.parameters[position].1
+    }
+}
+
+impl Constraint {
+    /// Panics if the constraint is malformed, like route patterns.
+    fn parse(source: &str) -> Constraint {
+        let pattern = match source {
+            "int" => "[0-9]+",
+            "alpha" => "[a-zA-Z]+",
+            "alnum" => "[a-zA-Z0-9]+",
+            _ => source,
+        };
+        let mut chars = pattern.chars().peekable();
+        let mut atoms = Vec::new();
+
+        while let Some(c) = chars.next() {
+            let class = match c {
+                '.' => Class::Any,
+                '\\' => Class::Char(chars.next().unwrap_or_else(|| malformed(source))),
+                '[' => {
+                    let negated = chars.next_if_eq(&'^').is_some();
+                    let mut ranges = Vec::new();
+
+                    loop {
+                        let start = match chars.next().unwrap_or_else(|| malformed(source)) {
+                            ']' if !ranges.is_empty() => break,
+                            '\\' => chars.next().unwrap_or_else(|| malformed(source)),
+                            c => c,
+                        };
+
+                        // A `-` right before the closing `]` is literal, as in `[a-z-]`.
+                        let end = match chars.peek() {
+                            Some('-') => {
+                                chars.next();
+
+                                match chars.next_if(|&c| c != ']') {
+                                    Some(end) => end,
+                                    None => {
+                                        ranges.push(('-', '-'));
+                                        start
+                                    }
+                                }
+                            }
+                            _ => start,
+                        };
+
+                        ranges.push((start, end));
+                    }
+
+                    Class::Set { negated, ranges }
+                }
+                '?' | '*' | '+' | '{' | ']' => malformed(source),
+                c => Class::Char(c),
+            };
+
+            let (min, max) = match chars.next_if(|c| matches!(c, '?' | '*' | '+' | '{')) {
+                Some('?') => (0, 1),
+                Some('*') => (0, usize::MAX),
+                Some('+') => (1, usize::MAX),
+                Some(_) => {
+                    let bounds: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    let parse = |s: &str| {
+                        s.trim()
+                            .parse::<usize>()
+                            .unwrap_or_else(|_| malformed(source))
+                    };
+
+                    match bounds.split_once(',') {
+                        Some((min, "")) => (parse(min), usize::MAX),
+                        Some((min, max)) => (parse(min), parse(max)),
+                        None => (parse(&bounds), parse(&bounds)),
+                    }
+                }
+                None => (1, 1),
+            };
+
+            atoms.push(Atom { class, min, max });
+        }
+
+        Constraint {
+            source: source.to_string(),
+            atoms,
+        }
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        let chars: Vec<char> = value.chars().collect();
+
+        match_atoms(&self.atoms, &chars)
+    }
+}
+
+fn malformed<T>(source: &str) -> T {
+    panic!("Route constraint `{source}` is malformed")
+}
+
+/// Matches greedily, backing off one repetition at a time when the rest doesn't match.
+fn match_atoms(atoms: &[Atom], chars: &[char]) -> bool {
+    let Some((atom, rest)) = atoms.split_first() else {
+        return chars.is_empty();
+    };
+
+    let mut count = 0;
+
+    while count < atom.max && count < chars.len() && atom.class.matches(chars[count]) {
+        count += 1;
+    }
+
+    while count >= atom.min {
+        if match_atoms(rest, &chars[count..]) {
+            return true;
+        }
+
+        if count == 0 {
+            break;
+        }
+
+        count -= 1;
+    }
+
+    false
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Class::Any => true,
+            Class::Char(expected) => c == *expected,
+            Class::Set { negated, ranges } => {
+                ranges.iter().any(|&(start, end)| start <= c && c <= end) != *negated
+            }
+        }
+    }
+}
+
+impl<E> Default for Router<E> {
+    fn default() -> Router<E> {
+        Router::new()
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment<'_>> {
+    let path = pattern
+        .strip_prefix('/')
+        .unwrap_or_else(|| panic!("Route pattern `{pattern}` must start with `/`"));
+
+    path.split('/')
+        .map(|segment| {
+            match segment
+                .strip_prefix('{')
+                .and_then(|name| name.strip_suffix('}'))
+            {
+                Some(name) => {
+                    let (name, constraint) = match name.split_once(':') {
+                        Some((name, constraint)) => (name, Some(constraint)),
+                        None => (name, None),
+                    };
+
+                    match name.strip_suffix('*') {
+                        Some(name) if constraint.is_none() => Segment::Wildcard(name),
+                        Some(_) => panic!("Wildcard parameter can't be constrained in `{pattern}`"),
+                        None => Segment::Parameter(name, constraint),
+                    }
+                }
+                None => Segment::Literal(segment),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{Next, Request, Response, Router};
+
+    fn router() -> Router<()> {
+        let mut router = Router::new();
+
+        router.add("GET /", |_| Ok(Response::plain_text("home".to_string())));
+        router.add("GET /posts/new", |_| {
+            Ok(Response::plain_text("new".to_string()))
+        });
+        router.add("GET /posts/{id}", |request| {
+            Ok(Response::plain_text(format!(
+                "show {}",
+                request.parameters["id"]
+            )))
+        });
+        router.add("DELETE /posts/{post}", |request| {
+            Ok(Response::plain_text(format!(
+                "delete {}",
+                request.parameters["post"]
+            )))
+        });
+        router.add("GET /files/{path*}", |request| {
+            Ok(Response::plain_text(format!(
+                "file {}",
+                request.parameters["path"]
+            )))
+        });
+
+        router
+    }
+
+    fn handle(method: &str, uri: &str) -> Response {
+        let mut request = Request::new(method.to_string(), uri.to_string());
+
+        router().handle(&mut request).unwrap()
+    }
+
+    #[test]
+    fn it_prefers_literal_segments_over_parameters() {
+        assert_eq!(handle("GET", "/posts/new").body.as_bytes(), b"new");
+        assert_eq!(handle("GET", "/posts/42?x=1").body.as_bytes(), b"show 42");
+        assert_eq!(handle("DELETE", "/posts/42").body.as_bytes(), b"delete 42");
+        assert_eq!(
+            handle("GET", "/files/a/b%20c").body.as_bytes(),
+            b"file a/b c"
+        );
+        assert_eq!(handle("GET", "/").body.as_bytes(), b"home");
+    }
+
+    #[test]
+    fn it_answers_405_with_allowed_methods() {
+        let response = handle("PUT", "/posts/42");
+
+        assert_eq!(response.status, 405);
+        assert_eq!(
+            response.headers.get("Allow").unwrap(),
+            "DELETE, GET, HEAD, OPTIONS"
+        );
+        assert_eq!(handle("GET", "/missing").status, 404);
+    }
+
+    #[test]
+    fn it_answers_head_and_options_requests() {
+        let response = handle("HEAD", "/posts/42");
+        assert_eq!(response.status, 200);
+        assert!(response.body.is_empty());
+
+        let mut output = Vec::new();
+        handle("HEAD", "/posts/42").send(&mut output).unwrap();
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .ends_with("Content-Length: 7\r\n\r\n"));
+
+        let response = handle("OPTIONS", "/posts/42");
+        assert_eq!(response.status, 204);
+        assert_eq!(
+            response.headers.get("Allow").unwrap(),
+            "DELETE, GET, HEAD, OPTIONS"
+        );
+        assert_eq!(handle("OPTIONS", "/missing").status, 404);
+    }
+
+    #[test]
+    fn it_falls_through_unmet_constraints() {
+        let mut router: Router<()> = Router::new();
+
+        router.get("/posts/{id:int}", |request| {
+            let id = request.param::<i64>("id").unwrap();
+
+            Ok(Response::plain_text(format!("id {}", id + 1)))
+        });
+        router.get("/posts/{slug:[a-z-]+}", |request| {
+            Ok(Response::plain_text(
+                request.param::<String>("slug").unwrap(),
+            ))
+        });
+        router.get("/archive/{year:[0-9]{4}}/{rest:[^.]*\\.html?}", |_| {
+            Ok(Response::plain_text("archive".to_string()))
+        });
+
+        let handle = |uri: &str| {
+            let mut request = Request::new("GET".to_string(), uri.to_string());
+            let response = router.handle(&mut request).unwrap();
+
+            (response.status, response.body.as_bytes().to_vec())
+        };
+
+        assert_eq!(handle("/posts/41"), (200, b"id 42".to_vec()));
+        assert_eq!(handle("/posts/hello-world"), (200, b"hello-world".to_vec()));
+        assert_eq!(handle("/posts/Hello").0, 404);
+        assert_eq!(handle("/archive/2024/a.htm").0, 200);
+        assert_eq!(handle("/archive/2024/.html").0, 200);
+        assert_eq!(handle("/archive/24/a.html").0, 404);
+        assert_eq!(handle("/archive/2024/a.b.html").0, 404);
+    }
+
+    #[test]
+    fn it_applies_nested_groups() {
+        let calls = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = |name: &'static str| {
+            let calls = calls.clone();
+
+            move |request: &mut Request, next: Next<()>| {
+                calls.lock().unwrap().push(name);
+                next.run(request)
+            }
+        };
+        let mut router: Router<()> = Router::new();
+
+        router
+            .group("/admin/")
+            .name("admin.")
+            .middleware(log("admin"))
+            .routes(|router| {
+                router
+                    .get("/", |_| Ok(Response::plain_text("dashboard".to_string())))
+                    .name("dashboard");
+                router
+                    .group("/users")
+                    .name("users.")
+                    .middleware(log("users"))
+                    .routes(|router| {
+                        router
+                            .get("/{id:int}", |_| {
+                                Ok(Response::plain_text("user".to_string()))
+                            })
+                            .middleware(log("route"))
+                            .name("show");
+                    });
+            });
+        router.get("/", |_| Ok(Response::plain_text("home".to_string())));
+
+        let urls = router.urls();
+        assert_eq!(urls.url_for("admin.dashboard", &[]).unwrap(), "/admin");
+        assert_eq!(
+            urls.url_for("admin.users.show", &[("id", "7")]).unwrap(),
+            "/admin/users/7"
+        );
+
+        let mut request = Request::new("GET".to_string(), "/admin/users/7".to_string());
+        assert_eq!(
+            router.handle(&mut request).unwrap().body.as_bytes(),
+            b"user"
+        );
+        assert_eq!(*calls.lock().unwrap(), vec!["admin", "users", "route"]);
+
+        let mut request = Request::new("GET".to_string(), "/".to_string());
+        assert_eq!(
+            router.handle(&mut request).unwrap().body.as_bytes(),
+            b"home"
+        );
+        assert_eq!(calls.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,469 @@
+use crate::http::{Body, Error, ErrorStatus, Limits, Request, Response, Router, StatusCode};
+use std::fmt::Debug;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often an idle connection checks whether other connections are waiting for its worker.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Accepts connections and handles them on a fixed pool of worker threads.
+///
+/// Accepted connections wait in a bounded queue until a worker is free. When the queue is full,
+/// the server stops accepting until there is room again, leaving further clients in the OS
+/// backlog. A panicking handler is answered with a `500` and doesn't take the worker down.
+///
+/// Connections are kept open between requests as HTTP/1.1 allows, so a worker serves one client
+/// until it closes the connection, stays idle for too long, or reaches the request limit. An idle
+/// connection is closed as soon as other connections are waiting, so that a few idle browsers
+/// can't hold every worker. The request head must arrive within `head_timeout` as a whole, so
+/// that a client trickling bytes can't hold a worker either.
+pub struct Server<E> {
+    addr: String,
+    workers: usize,
+    queue: usize,
+    connection: Connection<E>,
+}
+
+struct Connection<E> {
+    router: Router<E>,
+    limits: Limits,
+    idle_timeout: Duration,
+    head_timeout: Duration,
+    max_requests: usize,
+    /// The number of accepted connections waiting in the queue.
+    waiting: AtomicUsize,
+}
+
+impl<E: Debug + 'static> Server<E> {
+    /// Creates a server for `addr`, with as many workers as `APP_WORKERS` says, or one per CPU
+    /// if it isn't set.
+    pub fn new(addr: &str, router: Router<E>) -> Server<E> {
+        let workers = std::env::var("APP_WORKERS")
+            .ok()
+            .and_then(|workers| workers.parse().ok())
+            .filter(|&workers| workers > 0)
+            .unwrap_or_else(|| thread::available_parallelism().map_or(4, |n| n.get()));
+
+        // Built now, so that a route name given twice fails at startup, not on a request.
+        router.urls();
+
+        Server {
+            addr: addr.to_string(),
+            workers,
+            queue: workers * 16,
+            connection: Connection {
+                router,
+                limits: Limits::default(),
+                idle_timeout: Duration::from_secs(5),
+                head_timeout: Duration::from_secs(10),
+                max_requests: 100,
+                waiting: AtomicUsize::new(0),
+            },
+        }
+    }
+
+    pub fn workers(mut self, workers: usize) -> Server<E> {
+        self.workers = workers.max(1);
+
+        self
+    }
+
+    /// Sets how many accepted connections may wait for a free worker.
+    pub fn queue(mut self, queue: usize) -> Server<E> {
+        self.queue = queue;
+
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Server<E> {
+        self.connection.limits = limits;
+
+        self
+    }
+
+    /// Sets how long a kept-alive connection may wait for its next request before it's closed.
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Server<E> {
+        self.connection.idle_timeout = idle_timeout;
+
+        self
+    }
+
+    /// Sets how long a client may take to send the request line and headers, once it started.
+    pub fn head_timeout(mut self, head_timeout: Duration) -> Server<E> {
+        self.connection.head_timeout = head_timeout;
+
+        self
+    }
+
+    /// Sets how many requests a single connection may send before it's closed.
+    pub fn max_requests(mut self, max_requests: usize) -> Server<E> {
+        self.connection.max_requests = max_requests.max(1);
+
+        self
+    }
+
+    /// Binds the address and serves connections until the listener fails.
+    pub fn run(self) -> io::Result<()> {
+        let listener = TcpListener::bind(&self.addr)?;
+
+        self.serve(listener)
+    }
+
+    pub fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(self.queue);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let connection = Arc::new(self.connection);
+
+        for i in 0..self.workers {
+            let receiver = receiver.clone();
+            let connection = connection.clone();
+
+            thread::Builder::new()
+                .name(format!("http-worker-{i}"))
+                .spawn(move || loop {
+                    let stream = match receiver.lock().unwrap().recv() {
+                        Ok(stream) => stream,
+                        Err(_) => break,
+                    };
+
+                    connection.waiting.fetch_sub(1, Ordering::SeqCst);
+
+                    let result =
+                        panic::catch_unwind(AssertUnwindSafe(|| connection.handle(stream)));
+
+                    if let Ok(Err(err)) = result {
+                        eprintln!("Connection error: {err}");
+                    }
+                })?;
+        }
+
+        for stream in listener.incoming() {
+            match stream {
+                // Blocks while the queue is full, which is what holds back new clients.
+                Ok(stream) => {
+                    connection.waiting.fetch_add(1, Ordering::SeqCst);
+
+                    if sender.send(stream).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => eprintln!("Accept error: {err}"),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<E: Debug> Connection<E> {
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(Deadline {
+            stream: &stream,
+            deadline: None,
+            timeout: self.idle_timeout,
+        });
+        let mut served = 0;
+
+        loop {
+            if !self.wait_for_request(&mut reader, served > 0)? {
+                return Ok(());
+            }
+
+            reader.get_mut().deadline = Some(Instant::now() + self.head_timeout);
+
+            let head = Request::receive_headers(&mut reader, &self.limits);
+
+            reader.get_mut().deadline = None;
+            reader.get_mut().timeout = self.idle_timeout;
+
+            let mut request = match head.and_then(|mut request| {
+                request.receive_body(&mut reader, &self.limits)?;
+
+                Ok(request)
+            }) {
+                Ok(request) => request,
+                Err(Error::Io(err)) if is_timeout(&err) => {
+                    let mut response =
+                        Response::new(StatusCode::REQUEST_TIMEOUT, "Request Timeout");
+
+                    response.headers.insert_unchecked("Connection", "close");
+
+                    return response.send(&stream);
+                }
+                Err(Error::Io(err)) => return Err(err),
+                Err(err) => {
+                    // The rest of the input can't be trusted to start a new request.
+                    let mut response = error_response(&err);
+
+                    response.headers.insert_unchecked("Connection", "close");
+
+                    return response.send(&stream);
+                }
+            };
+
+            served += 1;
+
+            // A body framed both ways may have been framed otherwise by a proxy on the way, so
+            // the rest of the input can't be trusted (RFC 9112, section 6.1).
+            let keep_alive = request.keep_alive()
+                && !(request.headers.contains_key("transfer-encoding")
+                    && request.headers.contains_key("content-length"))
+                && served < self.max_requests
+                && self.waiting.load(Ordering::SeqCst) == 0;
+            let mut response = respond(&self.router, &mut request);
+
+            if request.method == "HEAD" {
+                response.omit_body();
+            }
+
+            // Without chunked encoding, only closing the connection ends a streamed body.
+            let keep_alive = keep_alive
+                && (request.version != "HTTP/1.0" || !matches!(response.body, Body::Stream(_)));
+
+            response.headers.insert_unchecked(
+                "Connection",
+                if keep_alive { "keep-alive" } else { "close" },
+            );
+            response.send_to(&stream, &request.version)?;
+
+            if !keep_alive {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Waits for the next request to start. Returns `false` if the client hangs up or stays
+    /// idle for too long, or, for a kept-alive connection, as soon as other connections are
+    /// waiting for a worker.
+    fn wait_for_request(
+        &self,
+        reader: &mut BufReader<Deadline>,
+        kept_alive: bool,
+    ) -> io::Result<bool> {
+        let idle_since = Instant::now();
+
+        reader.get_mut().timeout = POLL_INTERVAL;
+
+        loop {
+            match reader.fill_buf() {
+                Ok([]) => return Ok(false),
+                Ok(_) => return Ok(true),
+                Err(err) if is_timeout(&err) => {
+                    if idle_since.elapsed() >= self.idle_timeout
+                        || kept_alive && self.waiting.load(Ordering::SeqCst) > 0
+                    {
+                        return Ok(false);
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Reads from a connection with a timeout for each read or, once `deadline` is set, with
+/// whatever time is left until then, however the reads are spread out.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Option<Instant>,
+    timeout: Duration,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timeout = match self.deadline {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+            None => self.timeout,
+        };
+
+        if timeout.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        self.stream.set_read_timeout(Some(timeout))?;
+
+        let mut stream = self.stream;
+
+        stream.read(buf)
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Runs the router, turning both errors and panics into `500` responses.
+fn respond<E: Debug>(router: &Router<E>, request: &mut Request) -> Response {
+    match panic::catch_unwind(AssertUnwindSafe(|| router.handle(request))) {
+        Ok(Ok(response)) => response,
+        Ok(Err(err)) => {
+            // The error may tell of paths or queries, which are for the log only.
+            eprintln!("{} {} failed: {err:?}", request.method, request.uri);
+
+            Response::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+        }
+        Err(_) => Response::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"),
+    }
+}
+
+/// Answers requests that couldn't be received.
+fn error_response(err: &Error) -> Response {
+    let status = err.status();
+
+    Response::new(status, status.reason())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{Response, Router, Server};
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::time::{Duration, Instant};
+
+    fn get(addr: &str, uri: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut response = String::new();
+
+        write!(stream, "GET {uri} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        stream.read_to_string(&mut response).unwrap();
+
+        response
+    }
+
+    #[test]
+    fn it_serves_pipelined_requests_over_one_connection() {
+        let mut router: Router<()> = Router::new();
+        router.get("/{name}", |request| {
+            Ok(Response::plain_text(request.parameters["name"].clone()))
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = Server::new(&addr, router).workers(1);
+        std::thread::spawn(move || server.serve(listener));
+
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        let mut response = String::new();
+
+        write!(
+            stream,
+            "GET /first HTTP/1.1\r\n\r\nGET /second HTTP/1.1\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(response.contains("Connection: keep-alive\r\n"));
+        assert!(response.ends_with("second"));
+    }
+
+    #[test]
+    fn it_closes_connections_after_bodies_framed_both_ways() {
+        let mut router: Router<()> = Router::new();
+        router.post("/", |request| {
+            Ok(Response::plain_text(
+                String::from_utf8_lossy(&request.body).to_string(),
+            ))
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = Server::new(&addr, router).workers(1);
+        std::thread::spawn(move || server.serve(listener));
+
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        let mut response = String::new();
+
+        write!(
+            stream,
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n\
+             5\r\nhello\r\n0\r\n\r\nGET /smuggled HTTP/1.1\r\n\r\n"
+        )
+        .unwrap();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert_eq!(response.matches("HTTP/1.1").count(), 1);
+        assert!(response.contains("Connection: close\r\n"));
+        assert!(response.ends_with("hello"));
+    }
+
+    #[test]
+    fn it_closes_idle_connections_when_others_wait() {
+        let mut router: Router<()> = Router::new();
+        router.get("/", |_| Ok(Response::plain_text("home".to_string())));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = Server::new(&addr, router)
+            .workers(2)
+            .idle_timeout(Duration::from_secs(30));
+        std::thread::spawn(move || server.serve(listener));
+
+        // Keeps every worker busy with an idle, kept-alive connection.
+        let idle: Vec<TcpStream> = (0..2)
+            .map(|_| {
+                let mut stream = TcpStream::connect(&addr).unwrap();
+                write!(stream, "GET / HTTP/1.1\r\n\r\n").unwrap();
+                stream.read_exact(&mut [0; 17]).unwrap();
+                stream
+            })
+            .collect();
+
+        let started = Instant::now();
+        assert!(get(&addr, "/").contains("home"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        drop(idle);
+    }
+
+    #[test]
+    fn it_times_out_slow_request_heads() {
+        let router: Router<()> = Router::new();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = Server::new(&addr, router)
+            .workers(1)
+            .head_timeout(Duration::from_millis(300));
+        std::thread::spawn(move || server.serve(listener));
+
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        let mut response = String::new();
+
+        // Each byte comes well within the per-read timeout, but the head as a whole is too slow.
+        for byte in b"GET / HTTP/1.1\r\n" {
+            if stream.write_all(&[*byte]).is_err() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let _ = stream.read_to_string(&mut response);
+
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout"));
+    }
+
+    #[test]
+    fn it_survives_panicking_handlers() {
+        let mut router: Router<()> = Router::new();
+        router.get("/panic", |_| panic!("handler panicked"));
+        router.get("/", |_| Ok(Response::plain_text("home".to_string())));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = Server::new(&addr, router).workers(1);
+        std::thread::spawn(move || server.serve(listener));
+
+        assert!(get(&addr, "/panic").starts_with("HTTP/1.1 500 "));
+        assert!(get(&addr, "/").contains("home"));
+    }
+}
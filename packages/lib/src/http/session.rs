@@ -0,0 +1,651 @@
+use crate::crypt::Keys;
+use crate::http::{
+    percent_encode, Cookie, Error, Middleware, Next, Query, Request, Response, SameSite,
+};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Per-visitor data kept on the server between requests. Handlers reach it through
+/// `request.session` once the `Sessions` middleware is installed.
+#[derive(Debug, Clone)]
+pub struct Session {
+    id: String,
+    data: HashMap<String, String>,
+    previous_id: Option<String>,
+    is_new: bool,
+}
+
+impl Session {
+    fn new() -> Session {
+        Session {
+            id: generate_id(),
+            data: HashMap::new(),
+            previous_id: None,
+            is_new: true,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.data.get(key).map(|value| value.as_str())
+    }
+
+    pub fn has(&self, key: &str) -> bool {
+        self.data.contains_key(key)
+    }
+
+    pub fn put(&mut self, key: &str, value: &str) {
+        self.data.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.data.remove(key)
+    }
+
+    /// Returns the CSRF token of the session, creating it on first use.
+    pub fn token(&mut self) -> String {
+        if let Some(token) = self.data.get("_token") {
+            return token.clone();
+        }
+
+        let token = generate_id();
+
+        self.put("_token", &token);
+
+        token
+    }
+
+    /// Drops the CSRF token, so that `token` creates a new one.
+    pub fn regenerate_token(&mut self) {
+        self.data.remove("_token");
+    }
+
+    /// Removes all data, keeping the ID.
+    pub fn flush(&mut self) {
+        self.data.clear();
+    }
+
+    /// Moves the data under a new ID and forgets the old one. Call it whenever the visitor's
+    /// privileges change, such as on login, so that an ID planted before can't be reused.
+    pub fn regenerate(&mut self) {
+        if self.previous_id.is_none() {
+            self.previous_id = Some(self.id.clone());
+        }
+
+        self.id = generate_id();
+    }
+
+    /// Removes all data and regenerates the ID, as on logout.
+    pub fn invalidate(&mut self) {
+        self.flush();
+        self.regenerate();
+    }
+}
+
+/// Where session data lives between requests.
+pub trait Store: Send + Sync {
+    /// Returns the data of the session, or `None` if it doesn't exist or has expired.
+    fn load(&self, id: &str) -> Result<Option<HashMap<String, String>>, Error>;
+
+    fn save(
+        &self,
+        id: &str,
+        data: &HashMap<String, String>,
+        expires: SystemTime,
+    ) -> Result<(), Error>;
+
+    fn destroy(&self, id: &str) -> Result<(), Error>;
+
+    /// Removes all sessions that expired before `now`.
+    fn gc(&self, now: SystemTime) -> Result<(), Error>;
+}
+
+/// Keeps each session in its own file in a directory, such as `storage/sessions`.
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> FileStore {
+        FileStore { dir: dir.into() }
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+}
+
+impl Store for FileStore {
+    fn load(&self, id: &str) -> Result<Option<HashMap<String, String>>, Error> {
+        let contents = match std::fs::read_to_string(self.path(id)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::Io(e)),
+        };
+
+        let (expires, data) = contents.split_once('\n').unwrap_or((&contents, ""));
+
+        if parse_timestamp(expires) <= timestamp(SystemTime::now()) {
+            return Ok(None);
+        }
+
+        Ok(Some(decode(data)))
+    }
+
+    fn save(
+        &self,
+        id: &str,
+        data: &HashMap<String, String>,
+        expires: SystemTime,
+    ) -> Result<(), Error> {
+        std::fs::create_dir_all(&self.dir).map_err(Error::Io)?;
+
+        // Written aside and renamed, so that a concurrent request never reads half a session.
+        let mut temp = tempfile::NamedTempFile::new_in(&self.dir).map_err(Error::Io)?;
+
+        write!(temp, "{}\n{}", timestamp(expires), encode(data)).map_err(Error::Io)?;
+        temp.persist(self.path(id))
+            .map_err(|e| Error::Io(e.error))?;
+
+        Ok(())
+    }
+
+    fn destroy(&self, id: &str) -> Result<(), Error> {
+        match std::fs::remove_file(self.path(id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(Error::Io(e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn gc(&self, now: SystemTime) -> Result<(), Error> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(Error::Io(e)),
+        };
+
+        for entry in entries {
+            let entry = entry.map_err(Error::Io)?;
+
+            // Anything else in the directory, such as a save in progress, isn't a session.
+            if !entry.file_name().to_str().is_some_and(is_id) {
+                continue;
+            }
+
+            let path = entry.path();
+            let contents = std::fs::read_to_string(&path).unwrap_or_default();
+            let expires = contents.lines().next().unwrap_or("");
+
+            if parse_timestamp(expires) <= timestamp(now) {
+                // Another worker may have removed it first.
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps sessions in the `sessions` table of an SQLite database, creating it if needed.
+#[cfg(feature = "sqlite")]
+pub struct SqliteStore {
+    connection: std::sync::Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<SqliteStore, Error> {
+        let connection = rusqlite::Connection::open(path).map_err(sqlite_error)?;
+
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS sessions (
+                    id TEXT PRIMARY KEY,
+                    data TEXT NOT NULL,
+                    expires_at INTEGER NOT NULL
+                )",
+                (),
+            )
+            .map_err(sqlite_error)?;
+
+        Ok(SqliteStore {
+            connection: std::sync::Mutex::new(connection),
+        })
+    }
+
+    /// Opens the database the app uses, at `DB_PATH` or `storage/db.sqlite`.
+    pub fn from_env() -> Result<SqliteStore, Error> {
+        SqliteStore::open(std::env::var("DB_PATH").unwrap_or("storage/db.sqlite".to_string()))
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Store for SqliteStore {
+    fn load(&self, id: &str) -> Result<Option<HashMap<String, String>>, Error> {
+        use rusqlite::OptionalExtension;
+
+        let data: Option<String> = self
+            .connection()
+            .query_row(
+                "SELECT data FROM sessions WHERE id = ?1 AND expires_at > ?2",
+                (id, timestamp(SystemTime::now())),
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sqlite_error)?;
+
+        Ok(data.map(|data| decode(&data)))
+    }
+
+    fn save(
+        &self,
+        id: &str,
+        data: &HashMap<String, String>,
+        expires: SystemTime,
+    ) -> Result<(), Error> {
+        self.connection()
+            .execute(
+                "INSERT OR REPLACE INTO sessions (id, data, expires_at) VALUES (?1, ?2, ?3)",
+                (id, encode(data), timestamp(expires)),
+            )
+            .map_err(sqlite_error)?;
+
+        Ok(())
+    }
+
+    fn destroy(&self, id: &str) -> Result<(), Error> {
+        self.connection()
+            .execute("DELETE FROM sessions WHERE id = ?1", (id,))
+            .map_err(sqlite_error)?;
+
+        Ok(())
+    }
+
+    fn gc(&self, now: SystemTime) -> Result<(), Error> {
+        self.connection()
+            .execute(
+                "DELETE FROM sessions WHERE expires_at <= ?1",
+                (timestamp(now),),
+            )
+            .map_err(sqlite_error)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_error(e: rusqlite::Error) -> Error {
+    Error::Io(io::Error::other(e))
+}
+
+/// Loads the session named by a signed cookie before the rest of the pipeline runs, and saves it
+/// afterwards, refreshing the cookie. Requests without a valid cookie start a fresh session, which
+/// is only saved if something is put in it.
+pub struct Sessions<S> {
+    store: S,
+    keys: Keys,
+    cookie: String,
+    lifetime: Duration,
+    secure: bool,
+    gc_interval: usize,
+    requests: AtomicUsize,
+}
+
+impl<S: Store> Sessions<S> {
+    /// Creates the middleware. `keys` sign the session cookie.
+    pub fn new(store: S, keys: Keys) -> Sessions<S> {
+        Sessions {
+            store,
+            keys,
+            cookie: "session".to_string(),
+            lifetime: Duration::from_secs(2 * 60 * 60),
+            secure: false,
+            gc_interval: 100,
+            requests: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn cookie(mut self, name: &str) -> Sessions<S> {
+        self.cookie = name.to_string();
+
+        self
+    }
+
+    /// How long a session lives after the last request that used it.
+    pub fn lifetime(mut self, lifetime: Duration) -> Sessions<S> {
+        self.lifetime = lifetime;
+
+        self
+    }
+
+    /// Sends the cookie over HTTPS only.
+    pub fn secure(mut self, secure: bool) -> Sessions<S> {
+        self.secure = secure;
+
+        self
+    }
+
+    /// Removes expired sessions from the store once per this many requests.
+    pub fn gc_interval(mut self, gc_interval: usize) -> Sessions<S> {
+        self.gc_interval = gc_interval.max(1);
+
+        self
+    }
+
+    fn start(&self, request: &Request) -> Result<Session, Error> {
+        // The ID is checked to be hex, so that stores can use it as a file name.
+        let id = request
+            .signed_cookie(&self.cookie, &self.keys)
+            .filter(|id| is_id(id));
+
+        if let Some(id) = id {
+            if let Some(data) = self.store.load(&id)? {
+                return Ok(Session {
+                    id,
+                    data,
+                    previous_id: None,
+                    is_new: false,
+                });
+            }
+        }
+
+        Ok(Session::new())
+    }
+
+    fn finish(&self, session: &Session, response: &mut Response) -> Result<(), Error> {
+        if let Some(previous_id) = &session.previous_id {
+            self.store.destroy(previous_id)?;
+        }
+
+        self.store.save(
+            &session.id,
+            &session.data,
+            SystemTime::now() + self.lifetime,
+        )?;
+
+        response.cookie(
+            Cookie::signed(&self.cookie, &session.id, &self.keys)
+                .path("/")
+                .max_age(self.lifetime)
+                .secure(self.secure)
+                .http_only(true)
+                .same_site(SameSite::Lax),
+        )
+    }
+}
+
+impl<E: From<Error>, S: Store> Middleware<E> for Sessions<S> {
+    fn handle(&self, request: &mut Request, next: Next<E>) -> Result<Response, E> {
+        request.session = Some(self.start(request)?);
+
+        let result = next.run(request);
+        let session = request.session.take();
+        let mut response = result?;
+
+        // A new session nothing was put in isn't worth a file and a cookie.
+        if let Some(session) = session.filter(|s| !s.is_new || !s.data.is_empty()) {
+            self.finish(&session, &mut response)?;
+        }
+
+        if response.headers.contains_key("Set-Cookie") {
+            make_private(&mut response);
+        }
+
+        if self
+            .requests
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.gc_interval)
+        {
+            self.store.gc(SystemTime::now())?;
+        }
+
+        Ok(response)
+    }
+}
+
+/// Turns `Cache-Control: public` into `private`, so that shared caches don't hand one
+/// visitor's cookies to others.
+fn make_private(response: &mut Response) {
+    let Some(cache_control) = response.headers.get("Cache-Control") else {
+        return;
+    };
+    let directives: Vec<&str> = cache_control
+        .split(',')
+        .map(|directive| match directive.trim() {
+            d if d.eq_ignore_ascii_case("public") => "private",
+            d => d,
+        })
+        .collect();
+    let cache_control = directives.join(", ");
+
+    response
+        .headers
+        .insert_unchecked("Cache-Control", &cache_control);
+}
+
+fn generate_id() -> String {
+    let mut bytes = [0u8; 20];
+
+    getrandom::getrandom(&mut bytes).expect("the OS random number generator is unavailable");
+
+    hex(&bytes)
+}
+
+fn is_id(id: &str) -> bool {
+    !id.is_empty() && id.bytes().all(|c| c.is_ascii_hexdigit())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn encode(data: &HashMap<String, String>) -> String {
+    data.iter()
+        .map(|(key, value)| format!("{}={}", percent_encode(key), percent_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn decode(s: &str) -> HashMap<String, String> {
+    Query::parse(s)
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+fn timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn parse_timestamp(s: &str) -> u64 {
+    s.trim().parse().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileStore, Sessions, Store};
+    use crate::crypt::Keys;
+    use crate::http::{Error, Request, Response, Router};
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
+
+    fn router(dir: &std::path::Path) -> Router<Error> {
+        let mut router: Router<Error> = Router::new();
+
+        router.middleware(Sessions::new(FileStore::new(dir), Keys::new(b"secret")));
+        router.get("/visit", |request| {
+            let session = request.session.as_mut().unwrap();
+            let visits = session.get("visits").unwrap_or("0").parse::<u32>().unwrap() + 1;
+
+            session.put("visits", &visits.to_string());
+
+            Ok(Response::plain_text(visits.to_string()))
+        });
+        router.get("/login", |request| {
+            request.session.as_mut().unwrap().regenerate();
+
+            Ok(Response::plain_text("ok".to_string()))
+        });
+
+        router
+    }
+
+    fn get(router: &Router<Error>, uri: &str, cookie: Option<&str>) -> (String, String) {
+        let mut request = Request::new("GET".to_string(), uri.to_string());
+
+        if let Some(cookie) = cookie {
+            request.headers.insert("Cookie", cookie).unwrap();
+        }
+
+        let response = router.handle(&mut request).unwrap();
+        let set_cookie = response.headers.get("Set-Cookie").unwrap();
+        let cookie = set_cookie.split(';').next().unwrap().to_string();
+
+        (
+            String::from_utf8_lossy(response.body.as_bytes()).to_string(),
+            cookie,
+        )
+    }
+
+    #[test]
+    fn it_keeps_data_between_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let router = router(dir.path());
+
+        let (body, cookie) = get(&router, "/visit", None);
+        assert_eq!(body, "1");
+
+        let (body, same) = get(&router, "/visit", Some(&cookie));
+        assert_eq!(body, "2");
+        assert_eq!(same, cookie);
+
+        let tampered = cookie.replace("session=", "session=0");
+        let (body, _) = get(&router, "/visit", Some(&tampered));
+        assert_eq!(body, "1");
+    }
+
+    #[test]
+    fn it_regenerates_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let router = router(dir.path());
+
+        let (_, cookie) = get(&router, "/visit", None);
+        let (_, regenerated) = get(&router, "/login", Some(&cookie));
+        assert_ne!(regenerated, cookie);
+
+        let (body, _) = get(&router, "/visit", Some(&regenerated));
+        assert_eq!(body, "2");
+
+        let (body, _) = get(&router, "/visit", Some(&cookie));
+        assert_eq!(body, "1");
+    }
+
+    #[test]
+    fn it_skips_untouched_new_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut router: Router<Error> = Router::new();
+
+        router.middleware(Sessions::new(
+            FileStore::new(dir.path()),
+            Keys::new(b"secret"),
+        ));
+        router.get("/", |_| {
+            let mut response = Response::plain_text("home".to_string());
+            response
+                .headers
+                .insert_unchecked("Cache-Control", "public, max-age=60");
+
+            Ok(response)
+        });
+        router.get("/visit", |request| {
+            request.session.as_mut().unwrap().put("visits", "1");
+
+            let mut response = Response::plain_text("1".to_string());
+            response
+                .headers
+                .insert_unchecked("Cache-Control", "public, max-age=60");
+
+            Ok(response)
+        });
+
+        let response = router
+            .handle(&mut Request::new("GET".to_string(), "/".to_string()))
+            .unwrap();
+        assert_eq!(response.headers.get("Set-Cookie"), None);
+        assert_eq!(
+            response.headers.get("Cache-Control"),
+            Some("public, max-age=60")
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let response = router
+            .handle(&mut Request::new("GET".to_string(), "/visit".to_string()))
+            .unwrap();
+        assert!(response.headers.get("Set-Cookie").is_some());
+        assert_eq!(
+            response.headers.get("Cache-Control"),
+            Some("private, max-age=60")
+        );
+    }
+
+    #[test]
+    fn it_collects_expired_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path());
+        let data = HashMap::from([("user".to_string(), "1".to_string())]);
+        let now = SystemTime::now();
+
+        store
+            .save("aa", &data, now - Duration::from_secs(60))
+            .unwrap();
+        store
+            .save("bb", &data, now + Duration::from_secs(60))
+            .unwrap();
+
+        assert!(store.load("aa").unwrap().is_none());
+        assert_eq!(store.load("bb").unwrap(), Some(data));
+
+        std::fs::write(dir.path().join(".gitignore"), "*\n").unwrap();
+
+        store.gc(now).unwrap();
+        assert!(!dir.path().join("aa").exists());
+        assert!(dir.path().join("bb").exists());
+        assert!(dir.path().join(".gitignore").exists());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn it_stores_sessions_in_sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = super::SqliteStore::open(dir.path().join("sessions.sqlite")).unwrap();
+        let data = HashMap::from([("user".to_string(), "1".to_string())]);
+        let now = SystemTime::now();
+
+        store
+            .save("aa", &data, now + Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(store.load("aa").unwrap(), Some(data.clone()));
+
+        store
+            .save("aa", &data, now - Duration::from_secs(60))
+            .unwrap();
+        assert!(store.load("aa").unwrap().is_none());
+
+        store.gc(now).unwrap();
+        store.destroy("aa").unwrap();
+    }
+}
@@ -0,0 +1,234 @@
+use crate::http::percent_encode;
+use std::collections::BTreeMap;
+
+/// The named routes of a router, for building URLs from route names rather than hard-coded
+/// paths. Handlers reach it through `request.urls`.
+#[derive(Debug, Default, Clone)]
+pub struct Urls {
+    routes: BTreeMap<String, NamedRoute>,
+}
+
+#[derive(Debug, Clone)]
+struct NamedRoute {
+    pattern: String,
+    methods: Vec<String>,
+}
+
+impl Urls {
+    /// Adds a named route. The same name may be given to routes of several methods sharing a
+    /// pattern, but not to different patterns.
+    pub(crate) fn add(&mut self, name: &str, method: &str, pattern: &str) {
+        let route = self
+            .routes
+            .entry(name.to_string())
+            .or_insert_with(|| NamedRoute {
+                pattern: pattern.to_string(),
+                methods: Vec::new(),
+            });
+
+        if route.pattern != pattern {
+            panic!(
+                "Route name `{name}` is given to both `{}` and `{pattern}`",
+                route.pattern
+            );
+        }
+
+        route.methods.push(method.to_string());
+    }
+
+    /// Builds the path of the named route, as in
+    /// `urls.url_for("posts.show", &[("post", "42")])` for `/posts/{post}`.
+    ///
+    /// Values are percent-encoded, except for the `/` separators of `{param*}` values.
+    /// Parameters the pattern doesn't mention are added as a query string. Returns `None` if
+    /// there is no such route or a parameter of the pattern is missing.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let route = self.routes.get(name)?;
+        let mut used = Vec::new();
+        let mut url = String::new();
+
+        for segment in route.pattern.split('/').skip(1) {
+            url.push('/');
+
+            let Some(name) = segment
+                .strip_prefix('{')
+                .and_then(|name| name.strip_suffix('}'))
+            else {
+                url.push_str(segment);
+                continue;
+            };
+
+            let name = name.split(':').next().unwrap_or(name);
+            let (name, wildcard) = match name.strip_suffix('*') {
+                Some(name) => (name, true),
+                None => (name, false),
+            };
+            let (_, value) = params.iter().find(|(n, _)| *n == name)?;
+
+            used.push(name);
+
+            if wildcard {
+                let segments: Vec<String> = value.split('/').map(percent_encode).collect();
+
+                url.push_str(&segments.join("/"));
+            } else {
+                url.push_str(&percent_encode(value));
+            }
+        }
+
+        let query: Vec<String> = params
+            .iter()
+            .filter(|(name, _)| !used.contains(name))
+            .map(|(name, value)| format!("{}={}", percent_encode(name), percent_encode(value)))
+            .collect();
+
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+
+        Some(url)
+    }
+
+    /// Exports the routes in the shape of Ziggy's route list, so a frontend `route()` helper
+    /// can build the same URLs:
+    /// `{"routes":{"posts.show":{"uri":"posts/{post}","methods":["GET"],"parameters":["post"]}}}`.
+    ///
+    /// Constraints are left out of the URIs. The result is safe to embed in a `<script>` tag.
+    pub fn to_json(&self) -> String {
+        let routes: Vec<String> = self
+            .routes
+            .iter()
+            .map(|(name, route)| {
+                let mut parameters = Vec::new();
+                let segments: Vec<String> = route
+                    .pattern
+                    .split('/')
+                    .skip(1)
+                    .map(|segment| {
+                        match segment
+                            .strip_prefix('{')
+                            .and_then(|name| name.strip_suffix('}'))
+                        {
+                            Some(name) => {
+                                let name = name.split(':').next().unwrap_or(name);
+
+                                parameters.push(json_string(name.trim_end_matches('*')));
+
+                                format!("{{{name}}}")
+                            }
+                            None => segment.to_string(),
+                        }
+                    })
+                    .collect();
+                let methods: Vec<String> = route.methods.iter().map(|m| json_string(m)).collect();
+
+                format!(
+                    "{}:{{\"uri\":{},\"methods\":[{}],\"parameters\":[{}]}}",
+                    json_string(name),
+                    json_string(&segments.join("/")),
+                    methods.join(","),
+                    parameters.join(",")
+                )
+            })
+            .collect();
+
+        format!("{{\"routes\":{{{}}}}}", routes.join(","))
+    }
+}
+
+/// Quotes a string for JSON. `<`, `>` and `&` are escaped too, so that the output can't close a
+/// `<script>` tag it's embedded in.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+
+    result.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c < ' ' || matches!(c, '<' | '>' | '&') => {
+                result.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{Request, Response, Router};
+
+    fn router() -> Router<()> {
+        let mut router = Router::new();
+
+        router
+            .get("/posts/{post:int}", |request| {
+                let url = request.urls.url_for("files", &[("path", "a b/c.txt")]);
+
+                Ok(Response::plain_text(url.unwrap()))
+            })
+            .name("posts.show");
+        router.delete("/posts/{post}", |_| {
+            Ok(Response::plain_text("deleted".to_string()))
+        });
+        router
+            .get("/files/{path*}", |_| {
+                Ok(Response::plain_text("file".to_string()))
+            })
+            .name("files");
+
+        router
+    }
+
+    #[test]
+    fn it_builds_urls_of_named_routes() {
+        let router = router();
+        let urls = router.urls();
+
+        assert_eq!(
+            urls.url_for("posts.show", &[("post", "42"), ("tab", "a&b")])
+                .unwrap(),
+            "/posts/42?tab=a%26b"
+        );
+        assert_eq!(urls.url_for("posts.show", &[]), None);
+        assert_eq!(urls.url_for("posts.index", &[]), None);
+
+        let mut request = Request::new("GET".to_string(), "/posts/1".to_string());
+        let response = router.handle(&mut request).unwrap();
+        assert_eq!(response.body.as_bytes(), b"/files/a%20b/c.txt");
+    }
+
+    #[test]
+    #[should_panic(expected = "Route name `posts.show` is given to both")]
+    fn it_rejects_names_given_to_different_patterns() {
+        let mut router = router();
+
+        router
+            .get("/articles/{post}", |_| {
+                Ok(Response::plain_text("article".to_string()))
+            })
+            .name("posts.show");
+
+        router.urls();
+    }
+
+    #[test]
+    fn it_exports_routes_to_json() {
+        assert_eq!(
+            router().urls().to_json(),
+            "{\"routes\":{\
+                \"files\":{\"uri\":\"files/{path*}\",\"methods\":[\"GET\"],\"parameters\":[\"path\"]},\
+                \"posts.show\":{\"uri\":\"posts/{post}\",\"methods\":[\"GET\"],\"parameters\":[\"post\"]}\
+            }}"
+        );
+    }
+}
@@ -1,3 +1,4 @@
 pub mod cli;
+pub mod crypt;
 pub mod env;
 pub mod http;